@@ -0,0 +1,101 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use time;
+
+/// Time base for recorded IQ data.
+///
+/// Time is derived from the number of samples that have been processed since the start
+/// of the recording. Elapsed time is calculated with integer math in nanoseconds,
+/// so it does not drift no matter how many samples are counted.
+pub struct SampleClock {
+    start: time::Tm,
+    samplerate: u32,
+    samples: u64,
+}
+
+impl SampleClock {
+    pub fn new(start: time::Tm, samplerate: u32) -> SampleClock {
+        SampleClock {
+            start,
+            samplerate,
+            samples: 0,
+        }
+    }
+
+    pub fn advance(&mut self, samples: usize) {
+        self.samples += samples as u64;
+    }
+
+    /// Number of samples counted so far.
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+
+    /// Time elapsed since the start of the recording.
+    pub fn elapsed(&self) -> time::Duration {
+        self.duration(self.samples)
+    }
+
+    /// Time of the current sample.
+    pub fn now(&self) -> time::Tm {
+        self.at(0)
+    }
+
+    /// Time of the sample that is `offset` samples after the current one.
+    pub fn at(&self, offset: u64) -> time::Tm {
+        self.start + self.duration(self.samples + offset)
+    }
+
+    fn duration(&self, samples: u64) -> time::Duration {
+        let samplerate = self.samplerate as u64;
+        let seconds = samples / samplerate;
+        let nanoseconds = samples % samplerate * 1_000_000_000 / samplerate;
+
+        time::Duration::seconds(seconds as i64) + time::Duration::nanoseconds(nanoseconds as i64)
+    }
+}
+
+/// Formats UTC time as Y-m-dTH:M:S.fffZ: eg. 2015-01-22T09:07:16.250Z
+pub fn format_time(tm: &time::Tm) -> String {
+    let tm = tm.to_utc();
+    format!("{}.{:03}Z", tm.strftime("%Y-%m-%dT%H:%M:%S").unwrap(), tm.tm_nsec / 1_000_000)
+}
+
+#[test]
+fn test_sample_clock() {
+    let start = time::strptime("2015-01-22T09:07:16", "%Y-%m-%dT%H:%M:%S").unwrap();
+    let mut clock = SampleClock::new(start, 1024000);
+
+    clock.advance(1023);
+    assert_eq!(clock.elapsed(), time::Duration::nanoseconds(999_023));
+
+    // a full day of samples does not drift
+    for _ in 0..86400 {
+        clock.advance(1024000);
+    }
+    assert_eq!(clock.now() - start, time::Duration::days(1) + time::Duration::nanoseconds(999_023));
+    assert_eq!(clock.at(1) - start, time::Duration::days(1) + time::Duration::milliseconds(1));
+    assert_eq!(format_time(&clock.at(1)), "2015-01-23T09:07:16.001Z");
+}
@@ -0,0 +1,300 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+// Doppler correction that can be embedded in other programs.
+//
+// Doppler is predicted from TLE on fixed sample indexes and correction is applied with an NCO that
+// keeps its phase over block boundaries, so output does not depend on how input is split into blocks.
+
+use num::complex::Complex;
+use time;
+pub use gpredict::{Tle, Location};
+
+use clock::SampleClock;
+use dsp::Nco;
+use parallel::Block;
+use prediction::{Prediction, Predictor};
+
+use std::cmp;
+
+pub const DEFAULT_UPDATE_RATE: f64 = 100.0;
+
+/// How correction frequency changes between doppler updates.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Sweep {
+    /// Frequency is kept constant until the next update.
+    Step,
+    /// Frequency is swept linearly to the doppler predicted for the next update.
+    Chirp,
+    /// Frequency changes at the doppler rate predicted from range acceleration,
+    /// signal stays stationary even if doppler changes quickly near TCA.
+    Rate,
+}
+
+/// Prediction at the moment when correction was updated.
+#[derive(Clone)]
+pub struct Update {
+    // index of the first sample that correction applies to
+    pub sample: u64,
+    pub prediction: Prediction,
+}
+
+/// Removes doppler shift of a satellite signal from IQ samples.
+pub struct DopplerCorrector {
+    predictor: Predictor,
+    offset: f64,
+    samplerate: u32,
+    sweep: Sweep,
+    // None in realtime mode where system time is used
+    clock: Option<SampleClock>,
+    sample: u64,
+    update_samples: u64,
+    next_update: u64,
+    nco: Nco,
+    updates: Vec<Update>,
+}
+
+impl DopplerCorrector {
+    /// Corrector for a recording that started at `start` (UTC), time of each sample is calculated from its index.
+    pub fn new(tle: &Tle, location: &Location, frequency: f64, samplerate: u32, start: time::Tm) -> DopplerCorrector {
+        let mut corrector = DopplerCorrector::realtime(tle, location, frequency, samplerate);
+        corrector.clock = Some(SampleClock::new(start, samplerate));
+        corrector
+    }
+
+    /// Corrector for a live stream, doppler is predicted for the current system time.
+    pub fn realtime(tle: &Tle, location: &Location, frequency: f64, samplerate: u32) -> DopplerCorrector {
+        let mut corrector = DopplerCorrector {
+            predictor: Predictor::new(tle, location, frequency),
+            offset: 0.0,
+            samplerate,
+            sweep: Sweep::Step,
+            clock: None,
+            sample: 0,
+            update_samples: 1,
+            next_update: 0,
+            nco: Nco::new(),
+            updates: vec![],
+        };
+
+        corrector.set_update_rate(DEFAULT_UPDATE_RATE);
+        corrector
+    }
+
+    /// Constant frequency shift in Hz that is added to the correction.
+    pub fn set_offset(&mut self, offset_hz: f64) {
+        self.offset = offset_hz;
+    }
+
+    /// How many times per second doppler is predicted, takes effect from the next update.
+    pub fn set_update_rate(&mut self, rate_hz: f64) {
+        self.update_samples = ((self.samplerate as f64 / rate_hz).round() as u64).max(1);
+    }
+
+    /// How correction changes between updates, it is kept constant by default.
+    pub fn set_sweep(&mut self, sweep: Sweep) {
+        self.sweep = sweep;
+    }
+
+    /// Number of samples processed so far.
+    pub fn samples(&self) -> u64 {
+        self.sample
+    }
+
+    /// Updates that were made during the last `process` or `schedule` call.
+    pub fn updates(&self) -> &[Update] {
+        &self.updates
+    }
+
+    /// Writes corrected `input` samples to `output`, both must have the same length.
+    pub fn process(&mut self, input: &[Complex<f32>], output: &mut [Complex<f32>]) {
+        assert_eq!(input.len(), output.len());
+        output.copy_from_slice(input);
+        self.process_in_place(output);
+    }
+
+    pub fn process_in_place(&mut self, samples: &mut [Complex<f32>]) {
+        self.advance(samples.len(), |nco, _, position, count| {
+            nco.mix_in_place(&mut samples[position..position + count]);
+        });
+    }
+
+    /// Predicts doppler for the next `samples` samples without correcting them.
+    /// Returned blocks hold NCO state at their beginning and can be corrected with `parallel::process`.
+    pub fn schedule(&mut self, samples: usize) -> Vec<Block> {
+        let mut blocks = vec![];
+        self.advance(samples, |nco, sample, _, count| {
+            blocks.push(Block {
+                sample,
+                samples: count,
+                nco: nco.clone(),
+            });
+            nco.skip(count);
+        });
+
+        blocks
+    }
+
+    /// Splits next `samples` samples on update points and calls `f` with NCO, sample index, position and length of each part.
+    fn advance<F>(&mut self, samples: usize, mut f: F) where F: FnMut(&mut Nco, u64, usize, usize) {
+        self.updates.clear();
+
+        let mut position = 0;
+        while position < samples {
+            if self.sample == self.next_update {
+                self.update();
+                self.next_update += self.update_samples;
+            }
+
+            let count = cmp::min(samples - position, (self.next_update - self.sample) as usize);
+            f(&mut self.nco, self.sample, position, count);
+
+            position += count;
+            self.sample += count as u64;
+            if let Some(ref mut clock) = self.clock {
+                clock.advance(count);
+            }
+        }
+    }
+
+    /// Predicts doppler for the current sample and sets correction until the next update.
+    fn update(&mut self) {
+        let now = match self.clock {
+            Some(ref clock) => clock.now(),
+            None => time::now_utc(),
+        };
+
+        let prediction = self.predictor.at(now);
+        let start_hz = prediction.doppler_hz;
+        let rate_hz_sec = prediction.doppler_rate_hz_sec;
+
+        self.updates.push(Update {
+            sample: self.sample,
+            prediction,
+        });
+
+        // NCO keeps its phase, only frequency and its rate of change are set
+        match self.sweep {
+            Sweep::Step => {
+                self.nco.set_sweep(start_hz + self.offset, start_hz + self.offset, self.samplerate, self.update_samples as usize);
+            },
+            Sweep::Chirp => {
+                let end = match self.clock {
+                    Some(ref clock) => clock.at(self.update_samples),
+                    None => now + time::Duration::nanoseconds((self.update_samples as f64 / self.samplerate as f64 * 1e9) as i64),
+                };
+
+                let end_hz = self.predictor.doppler_hz(end);
+                self.nco.set_sweep(start_hz + self.offset, end_hz + self.offset, self.samplerate, self.update_samples as usize);
+            },
+            Sweep::Rate => {
+                self.nco.set_rate(start_hz + self.offset, rate_hz_sec, self.samplerate);
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+pub fn test_corrector(sweep: Sweep) -> DopplerCorrector {
+    let tle = Tle {
+        name: "ESTCUBE 1".to_string(),
+        line1: "1 39161U 13021C   15022.37999305  .00001154  00000-0  18779-3 0  9998".to_string(),
+        line2: "2 39161  98.1065 103.1185 0010366 111.6127 248.6189 14.69497044903665".to_string(),
+    };
+    let location = Location{lat_deg: 58.26541, lon_deg: 26.46667, alt_m: 76.0};
+    let start = ::usage::parse_time("2015-01-22T09:07:16.250").unwrap();
+
+    let mut corrector = DopplerCorrector::new(&tle, &location, 437505000.0, 48000, start);
+    corrector.set_offset(-2500.0);
+    corrector.set_update_rate(10.0);
+    corrector.set_sweep(sweep);
+    corrector
+}
+
+#[cfg(test)]
+fn test_samples(count: usize) -> Vec<Complex<f32>> {
+    (0..count as u32).map(|n| {
+        let n = n.wrapping_mul(2654435761);
+        Complex::new((n >> 20) as f32 / 2048.0 - 1.0, (n & 0xFFF) as f32 / 2048.0 - 1.0)
+    }).collect()
+}
+
+#[test]
+fn test_block_size_independence() {
+    let input = test_samples(48000);
+
+    for sweep in &[Sweep::Step, Sweep::Chirp, Sweep::Rate] {
+        let mut whole = vec![Complex::new(0.0, 0.0); input.len()];
+        test_corrector(*sweep).process(&input, &mut whole);
+
+        // blocks that are smaller and larger than update interval and do not line up with it
+        let mut corrector = test_corrector(*sweep);
+        let mut blocks = input.clone();
+        let mut position = 0;
+        for (n, size) in [1, 333, 4800, 77, 2048, 6000].iter().cycle().enumerate() {
+            let end = cmp::min(position + size, input.len());
+            if n % 2 == 0 {
+                corrector.process_in_place(&mut blocks[position..end]);
+            }
+            else {
+                corrector.process(&input[position..end], &mut blocks[position..end]);
+            }
+
+            position = end;
+            if position == input.len() {
+                break;
+            }
+        }
+
+        assert_eq!(corrector.samples(), input.len() as u64);
+        assert!(blocks == whole, "{:?} output depends on block size", sweep);
+    }
+}
+
+#[test]
+fn test_sweep_rate() {
+    let samplerate = 48000.0;
+    let input = vec![Complex::<f32>::new(1.0, 0.0); 48000];
+
+    let mut corrector = test_corrector(Sweep::Rate);
+    let mut output = vec![Complex::new(0.0, 0.0); input.len()];
+    corrector.process(&input, &mut output);
+
+    let updates = corrector.updates().to_vec();
+    assert_eq!(updates.len(), 10);
+
+    // within an update interval correction frequency changes at predicted doppler rate,
+    // instantaneous frequency is taken from phase difference of successive samples
+    for (n, update) in updates.iter().enumerate() {
+        let start = update.sample as usize;
+        let end = updates.get(n + 1).map(|u| u.sample as usize).unwrap_or(output.len());
+
+        for k in start..end - 1 {
+            let frequency = -(output[k + 1] * output[k].conj()).arg() as f64 * samplerate / (2.0 * ::std::f64::consts::PI);
+            let elapsed = (k - start) as f64 / samplerate;
+            let expected = update.prediction.doppler_hz - 2500.0 + update.prediction.doppler_rate_hz_sec * elapsed;
+            assert!((frequency - expected).abs() < 0.1, "{} Hz instead of {} Hz", frequency, expected);
+        }
+    }
+}
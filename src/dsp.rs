@@ -22,29 +22,22 @@
  * SOFTWARE.
  */
 
-use liquid_dsp::LiquidComplex32;
-
 use num::complex::Complex;
-use std::mem;
+use usage::{DataType, Endianness};
 
-// Rust does not support C complex numbers in the same way on 32 and 64 bit platforms:
-// https://github.com/rust-lang/rfcs/issues/793
-// Therefore this workaround is needed.
-//
-// Type `LiquidComplex32` is used here because standard `num::complex::Complex` does not have #[repr(C)].
-// Function `ccexpf` is implemented in complex.c
-//
-// Notice that `ccexpf` does not return value, it just changes `z` value in place.
-// For some reson it did not work correctly on ARM (BeagleBoneBlack) if `ccexpf` returned
-// calculated `LiquidComplex32` struct. Although it worked on Mac OS X and 64/32 bit X86 Ubuntu.
-extern {
-    pub fn ccexpf(z: *mut LiquidComplex32);
-}
+use std::f64::consts::PI;
 
-use std::f32::consts::PI;
+/// Complex exponential function, same as `cexpf` from C99 complex.h.
+pub fn cexpf(z: Complex<f32>) -> Complex<f32> {
+    let r = z.re.exp();
 
-#[cfg(test)]
-use std;
+    if z.im == 0.0 {
+        // avoid inf * 0 = NaN if real part overflows
+        return Complex::<f32>::new(r, z.im);
+    }
+
+    Complex::<f32>::new(r * z.im.cos(), r * z.im.sin())
+}
 
 #[cfg(test)]
 fn assert_eq_delta(a: f32, b: f32, delta: f32) {
@@ -56,81 +49,673 @@ fn assert_eq_delta(a: f32, b: f32, delta: f32) {
 
 #[test]
 fn test_cexpf() {
-    // cargo test -- --nocapture
-    // to see prints
-
-    let mut a = Complex::<f32>::new(0.0, 0.0);
-    unsafe {ccexpf(mem::transmute(&mut a))};
+    let a = cexpf(Complex::<f32>::new(0.0, 0.0));
     assert_eq_delta(a.re, 1.0, 0.000001);
     assert_eq_delta(a.im, 0.0, 0.000001);
 
-    let mut a = Complex::<f32>::new(1.0, 1.0);
-    unsafe {ccexpf(mem::transmute(&mut a))};
+    let a = cexpf(Complex::<f32>::new(1.0, 1.0));
     assert_eq_delta(a.re, 1.468694, 0.000001);
     assert_eq_delta(a.im, 2.2873552, 0.000001);
 
-    let mut a = Complex::<f32>::new(70.0, 70.0);
-    unsafe {ccexpf(mem::transmute(&mut a))};
+    let a = cexpf(Complex::<f32>::new(70.0, 70.0));
     assert_eq_delta(a.re, 1593075600000000000000000000000f32, 0.000001);
     assert_eq_delta(a.im, 1946674600000000000000000000000f32, 0.000001);
 
-    let mut a = Complex::<f32>::new(1_000_000.0, 1_000_000.0);
-    unsafe {ccexpf(mem::transmute(&mut a))};
-    assert_eq!(a.re, std::f32::INFINITY);
-    assert_eq!(a.im, -std::f32::INFINITY);
+    let a = cexpf(Complex::<f32>::new(1_000_000.0, 1_000_000.0));
+    assert_eq!(a.re, f32::INFINITY);
+    assert_eq!(a.im, -f32::INFINITY);
+}
+
+fn read_uint(b: &[u8], endianness: Endianness) -> u64 {
+    let mut value: u64 = 0;
+
+    match endianness {
+        Endianness::Little => for byte in b.iter().rev() { value = value << 8 | *byte as u64; },
+        Endianness::Big => for byte in b.iter() { value = value << 8 | *byte as u64; },
+    }
+
+    value
+}
+
+fn push_uint(output: &mut Vec<u8>, value: u64, bytes: usize, endianness: Endianness) {
+    match endianness {
+        Endianness::Little => for n in 0..bytes { output.push((value >> (n * 8) & 0xFF) as u8); },
+        Endianness::Big => for n in (0..bytes).rev() { output.push((value >> (n * 8) & 0xFF) as u8); },
+    }
+}
 
-    //println!("a={:?}", a);
+/// Output stage for integer sample types.
+///
+/// Saturates scaled sample values to the range of the output type instead of letting them
+/// wrap around, optionally adds TPDF dither before rounding and counts clipped samples.
+pub struct Quantizer {
+    dither: bool,
+    clipped: u64,
+    position: u64,
 }
 
-pub fn convert_iqi16_to_complex(inbuf: &[u8]) -> Vec<Complex<f32>> {
+impl Quantizer {
+    pub fn new(dither: bool) -> Quantizer {
+        Quantizer {
+            dither,
+            clipped: 0,
+            position: 0,
+        }
+    }
+
+    /// Quantizer for a stream that starts from `sample`, dither noise is the same
+    /// as if all samples before it had gone through one quantizer.
+    pub fn at(dither: bool, sample: u64) -> Quantizer {
+        Quantizer {
+            dither,
+            clipped: 0,
+            // I and Q get their own noise value
+            position: sample.wrapping_mul(2),
+        }
+    }
+
+    /// Number of samples where I or Q had to be clipped.
+    pub fn clipped(&self) -> u64 {
+        self.clipped
+    }
+
+    // Triangular noise in range (-1, 1) LSB as a sum of two uniform random values.
+    // Noise is derived from value position with splitmix64, so it is reproducible.
+    fn tpdf(&mut self) -> f64 {
+        self.position = self.position.wrapping_add(1);
+
+        let mut z = self.position.wrapping_mul(0x9E3779B97F4A7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z = z ^ (z >> 31);
+
+        let a = (z >> 32) as f64 / 4294967296.;
+        let b = (z & 0xFFFFFFFF) as f64 / 4294967296.;
+        a - b
+    }
+
+    fn quantize(&mut self, value: f64, min: f64, max: f64) -> (f64, bool) {
+        let value = if self.dither {(value + self.tpdf()).round()} else {value.round()};
+
+        if value < min {
+            (min, true)
+        }
+        else if value > max {
+            (max, true)
+        }
+        else {
+            (value, false)
+        }
+    }
+
+    fn quantize_sample(&mut self, sample: &Complex<f32>, scale: f64, offset: f64, min: f64, max: f64) -> (f64, f64) {
+        let (i, clipped_i) = self.quantize(sample.re as f64 * scale + offset, min, max);
+        let (q, clipped_q) = self.quantize(sample.im as f64 * scale + offset, min, max);
+
+        if clipped_i || clipped_q {
+            self.clipped += 1;
+        }
+
+        (i, q)
+    }
+}
+
+/// Multiplies samples with linear gain that is given in dB.
+pub fn apply_gain(inbuf: &mut [Complex<f32>], gain_db: f32) {
+    let gain = 10_f32.powf(gain_db / 20.0);
+
+    for sample in inbuf.iter_mut() {
+        *sample *= gain;
+    }
+}
+
+#[test]
+fn test_quantizer() {
+    let mut quantizer = Quantizer::new(false);
+    assert_eq!(quantizer.quantize_sample(&Complex::<f32>::new(0.5, -0.5), 100.0, 0.0, -100.0, 99.0), (50.0, -50.0));
+    assert_eq!(quantizer.quantize_sample(&Complex::<f32>::new(1.0, -1.0), 100.0, 0.0, -100.0, 99.0), (99.0, -100.0));
+    assert_eq!(quantizer.quantize_sample(&Complex::<f32>::new(0.0, -2.0), 100.0, 0.0, -100.0, 99.0), (0.0, -100.0));
+    assert_eq!(quantizer.clipped(), 2);
+
+    // dither noise stays within 1 LSB and averages out
+    let mut quantizer = Quantizer::new(true);
+    let mut sum = 0.0;
+    for _ in 0..10000 {
+        let (i, _) = quantizer.quantize_sample(&Complex::<f32>::new(0.25, 0.0), 1.0, 0.0, -100.0, 99.0);
+        assert!(i == 0.0 || i == 1.0 || i == -1.0);
+        sum += i;
+    }
+    assert!((sum / 10000.0 - 0.25).abs() < 0.02);
+    assert_eq!(quantizer.clipped(), 0);
+}
+
+// Every converter has an _into variant that clears and fills a given buffer,
+// so the processing loop can reuse its buffers instead of allocating new ones for every block.
+
+pub fn convert_iqi16_to_complex(inbuf: &[u8], endianness: Endianness) -> Vec<Complex<f32>> {
+    let mut output = Vec::<Complex<f32>>::new();
+    convert_iqi16_to_complex_into(inbuf, endianness, &mut output);
+    output
+}
+
+pub fn convert_iqi16_to_complex_into(inbuf: &[u8], endianness: Endianness, output: &mut Vec<Complex<f32>>) {
     // inbuf consists of i16 IQ pairs that are represented as bytes here
-    assert!(inbuf.len() % 4 == 0);
+    assert!(inbuf.len().is_multiple_of(4));
 
-    let mut output = Vec::<Complex<f32>>::with_capacity(inbuf.len()/8);
+    output.clear();
+    output.reserve(inbuf.len()/4);
 
     for b in inbuf.chunks(4) {
-        let i: f32 = ((b[1] as i16) << 8 | b[0] as i16) as f32 / 32768.;
-        let q: f32 = ((b[3] as i16) << 8 | b[2] as i16) as f32 / 32768.;
+        let i: f32 = (read_uint(&b[0..2], endianness) as i16) as f32 / 32768.;
+        let q: f32 = (read_uint(&b[2..4], endianness) as i16) as f32 / 32768.;
 
         output.push(Complex::<f32>::new(i, q));
     }
+}
 
+pub fn convert_complex_to_iqi16(inbuf: &[Complex<f32>], endianness: Endianness, quantizer: &mut Quantizer) -> Vec<u8> {
+    let mut output = Vec::<u8>::new();
+    convert_complex_to_iqi16_into(inbuf, endianness, quantizer, &mut output);
     output
 }
 
-pub fn convert_iqf32_to_complex(inbuf: &[u8]) -> Vec<Complex<f32>> {
+pub fn convert_complex_to_iqi16_into(inbuf: &[Complex<f32>], endianness: Endianness, quantizer: &mut Quantizer, output: &mut Vec<u8>) {
+    output.clear();
+    output.reserve(inbuf.len() * 4);
+
+    for sample in inbuf {
+        let (i, q) = quantizer.quantize_sample(sample, 32767.0, 0.0, -32768.0, 32767.0);
+        let (i, q) = (i as i16, q as i16);
+
+        push_uint(output, i as u16 as u64, 2, endianness);
+        push_uint(output, q as u16 as u64, 2, endianness);
+    }
+}
+
+#[test]
+fn test_convert_iqi16() {
+    let input = convert_iqi16_to_complex(&[0x00, 0x80, 0x00, 0x40], Endianness::Little);
+    assert_eq!(input[0], Complex::<f32>::new(-1.0, 0.5));
+    assert_eq!(convert_iqi16_to_complex(&[0x80, 0x00, 0x40, 0x00], Endianness::Big), input);
+
+    let mut quantizer = Quantizer::new(false);
+    assert_eq!(convert_complex_to_iqi16(&input, Endianness::Little, &mut quantizer), [0x01, 0x80, 0x00, 0x40]);
+    assert_eq!(convert_complex_to_iqi16(&input, Endianness::Big, &mut quantizer), [0x80, 0x01, 0x40, 0x00]);
+    assert_eq!(convert_complex_to_iqi16(&[Complex::<f32>::new(-2.0, 2.0)], Endianness::Little, &mut quantizer), [0x00, 0x80, 0xFF, 0x7F]);
+    assert_eq!(quantizer.clipped(), 1);
+}
+
+pub fn convert_iqf32_to_complex(inbuf: &[u8], endianness: Endianness) -> Vec<Complex<f32>> {
+    let mut output = Vec::<Complex<f32>>::new();
+    convert_iqf32_to_complex_into(inbuf, endianness, &mut output);
+    output
+}
+
+pub fn convert_iqf32_to_complex_into(inbuf: &[u8], endianness: Endianness, output: &mut Vec<Complex<f32>>) {
     // inbuf consists of f32 IQ pairs that are represented as bytes here
-    assert!(inbuf.len() % 8 == 0);
+    assert!(inbuf.len().is_multiple_of(8));
 
-    let mut output = Vec::<Complex<f32>>::with_capacity(inbuf.len()/8);
+    output.clear();
+    output.reserve(inbuf.len()/8);
 
     for b in inbuf.chunks(8) {
-        let i: f32 = unsafe {mem::transmute::<u32, f32>(((b[3] as u32) << 24) | ((b[2] as u32) << 16) | ((b[1] as u32) << 8) | b[0] as u32)};
-        let q: f32 = unsafe {mem::transmute::<u32, f32>(((b[7] as u32) << 24) | ((b[6] as u32) << 16) | ((b[5] as u32) << 8) | b[4] as u32)};
+        let i: f32 = f32::from_bits(read_uint(&b[0..4], endianness) as u32);
+        let q: f32 = f32::from_bits(read_uint(&b[4..8], endianness) as u32);
+
+        output.push(Complex::<f32>::new(i, q));
+    }
+}
+
+pub fn convert_complex_to_iqf32(inbuf: &[Complex<f32>], endianness: Endianness) -> Vec<u8> {
+    let mut output = Vec::<u8>::new();
+    convert_complex_to_iqf32_into(inbuf, endianness, &mut output);
+    output
+}
+
+pub fn convert_complex_to_iqf32_into(inbuf: &[Complex<f32>], endianness: Endianness, output: &mut Vec<u8>) {
+    output.clear();
+    output.reserve(inbuf.len() * 8);
+
+    for sample in inbuf {
+        push_uint(output, sample.re.to_bits() as u64, 4, endianness);
+        push_uint(output, sample.im.to_bits() as u64, 4, endianness);
+    }
+}
+
+#[test]
+fn test_convert_iqf32() {
+    let samples = [Complex::<f32>::new(-1.0, 0.25), Complex::<f32>::new(1e-3, -7.5)];
+
+    let bytes = convert_complex_to_iqf32(&samples, Endianness::Little);
+    assert_eq!(&bytes[0..4], &[0x00, 0x00, 0x80, 0xBF]);
+    assert_eq!(convert_iqf32_to_complex(&bytes, Endianness::Little), samples);
+
+    let bytes = convert_complex_to_iqf32(&samples, Endianness::Big);
+    assert_eq!(&bytes[0..4], &[0xBF, 0x80, 0x00, 0x00]);
+    assert_eq!(convert_iqf32_to_complex(&bytes, Endianness::Big), samples);
+}
+
+pub fn convert_iqu8_to_complex(inbuf: &[u8]) -> Vec<Complex<f32>> {
+    let mut output = Vec::<Complex<f32>>::new();
+    convert_iqu8_to_complex_into(inbuf, &mut output);
+    output
+}
+
+pub fn convert_iqu8_to_complex_into(inbuf: &[u8], output: &mut Vec<Complex<f32>>) {
+    // inbuf consists of unsigned u8 IQ pairs (rtl_sdr format), zero level is at 127.5
+    assert!(inbuf.len().is_multiple_of(2));
+
+    output.clear();
+    output.reserve(inbuf.len()/2);
+
+    for b in inbuf.chunks(2) {
+        let i: f32 = (b[0] as f32 - 127.5) / 127.5;
+        let q: f32 = (b[1] as f32 - 127.5) / 127.5;
 
         output.push(Complex::<f32>::new(i, q));
     }
+}
 
+pub fn convert_complex_to_iqu8(inbuf: &[Complex<f32>], quantizer: &mut Quantizer) -> Vec<u8> {
+    let mut output = Vec::<u8>::new();
+    convert_complex_to_iqu8_into(inbuf, quantizer, &mut output);
     output
 }
 
-pub fn shift_frequency(inbuf: &[Complex<f32>], samplenum: &mut u32, shift_hz: f32, samplerate: u32) -> Vec<Complex<f32>> {
-    let mut output = Vec::<Complex<f32>>::with_capacity(inbuf.len());
+pub fn convert_complex_to_iqu8_into(inbuf: &[Complex<f32>], quantizer: &mut Quantizer, output: &mut Vec<u8>) {
+    output.clear();
+    output.reserve(inbuf.len() * 2);
 
     for sample in inbuf {
-        let mut corrector = Complex::<f32>::new(0.0, -2. * PI * (shift_hz / samplerate as f32 * (*samplenum) as f32) as f32);
-        unsafe { ccexpf(mem::transmute(&mut corrector))};
-        output.push(sample * corrector);
+        let (i, q) = quantizer.quantize_sample(sample, 127.5, 127.5, 0.0, 255.0);
+        output.push(i as u8);
+        output.push(q as u8);
+    }
+}
+
+#[test]
+fn test_convert_iqu8() {
+    let input = convert_iqu8_to_complex(&[0, 255, 127, 128]);
+    assert_eq!(input[0], Complex::<f32>::new(-1.0, 1.0));
+    assert_eq!(input[1], Complex::<f32>::new(-0.5 / 127.5, 0.5 / 127.5));
+
+    let mut quantizer = Quantizer::new(false);
+    assert_eq!(convert_complex_to_iqu8(&input, &mut quantizer), [0, 255, 127, 128]);
+    assert_eq!(convert_complex_to_iqu8(&[Complex::<f32>::new(-2.0, 2.0)], &mut quantizer), [0, 255]);
+    assert_eq!(quantizer.clipped(), 1);
+}
+
+pub fn convert_iqi8_to_complex(inbuf: &[u8]) -> Vec<Complex<f32>> {
+    let mut output = Vec::<Complex<f32>>::new();
+    convert_iqi8_to_complex_into(inbuf, &mut output);
+    output
+}
+
+pub fn convert_iqi8_to_complex_into(inbuf: &[u8], output: &mut Vec<Complex<f32>>) {
+    // inbuf consists of signed i8 IQ pairs (hackrf_transfer format)
+    assert!(inbuf.len().is_multiple_of(2));
+
+    output.clear();
+    output.reserve(inbuf.len()/2);
 
-        if (shift_hz / samplerate as f32 * *samplenum as f32).fract() == 0.0 {
-            *samplenum = 1;
+    for b in inbuf.chunks(2) {
+        let i: f32 = (b[0] as i8) as f32 / 128.;
+        let q: f32 = (b[1] as i8) as f32 / 128.;
+
+        output.push(Complex::<f32>::new(i, q));
+    }
+}
+
+pub fn convert_complex_to_iqi8(inbuf: &[Complex<f32>], quantizer: &mut Quantizer) -> Vec<u8> {
+    let mut output = Vec::<u8>::new();
+    convert_complex_to_iqi8_into(inbuf, quantizer, &mut output);
+    output
+}
+
+pub fn convert_complex_to_iqi8_into(inbuf: &[Complex<f32>], quantizer: &mut Quantizer, output: &mut Vec<u8>) {
+    output.clear();
+    output.reserve(inbuf.len() * 2);
+
+    for sample in inbuf {
+        let (i, q) = quantizer.quantize_sample(sample, 127.0, 0.0, -128.0, 127.0);
+        output.push(i as i8 as u8);
+        output.push(q as i8 as u8);
+    }
+}
+
+#[test]
+fn test_convert_iqi8() {
+    let input = convert_iqi8_to_complex(&[0x80, 0x7F, 0x00, 0xFF]);
+    assert_eq!(input[0], Complex::<f32>::new(-1.0, 127.0 / 128.0));
+    assert_eq!(input[1], Complex::<f32>::new(0.0, -1.0 / 128.0));
+
+    let mut quantizer = Quantizer::new(false);
+    assert_eq!(convert_complex_to_iqi8(&input, &mut quantizer), [0x81, 0x7E, 0x00, 0xFF]);
+    assert_eq!(convert_complex_to_iqi8(&[Complex::<f32>::new(-2.0, 2.0)], &mut quantizer), [0x80, 0x7F]);
+    assert_eq!(quantizer.clipped(), 1);
+}
+
+pub fn convert_iqi32_to_complex(inbuf: &[u8], endianness: Endianness) -> Vec<Complex<f32>> {
+    let mut output = Vec::<Complex<f32>>::new();
+    convert_iqi32_to_complex_into(inbuf, endianness, &mut output);
+    output
+}
+
+pub fn convert_iqi32_to_complex_into(inbuf: &[u8], endianness: Endianness, output: &mut Vec<Complex<f32>>) {
+    // inbuf consists of i32 IQ pairs that are represented as bytes here
+    assert!(inbuf.len().is_multiple_of(8));
+
+    output.clear();
+    output.reserve(inbuf.len()/8);
+
+    for b in inbuf.chunks(8) {
+        let i: f32 = (read_uint(&b[0..4], endianness) as i32) as f32 / 2147483648.;
+        let q: f32 = (read_uint(&b[4..8], endianness) as i32) as f32 / 2147483648.;
+
+        output.push(Complex::<f32>::new(i, q));
+    }
+}
+
+pub fn convert_complex_to_iqi32(inbuf: &[Complex<f32>], endianness: Endianness, quantizer: &mut Quantizer) -> Vec<u8> {
+    let mut output = Vec::<u8>::new();
+    convert_complex_to_iqi32_into(inbuf, endianness, quantizer, &mut output);
+    output
+}
+
+pub fn convert_complex_to_iqi32_into(inbuf: &[Complex<f32>], endianness: Endianness, quantizer: &mut Quantizer, output: &mut Vec<u8>) {
+    output.clear();
+    output.reserve(inbuf.len() * 8);
+
+    for sample in inbuf {
+        let (i, q) = quantizer.quantize_sample(sample, 2147483647.0, 0.0, -2147483648.0, 2147483647.0);
+        let (i, q) = (i as i32, q as i32);
+
+        push_uint(output, i as u32 as u64, 4, endianness);
+        push_uint(output, q as u32 as u64, 4, endianness);
+    }
+}
+
+#[test]
+fn test_convert_iqi32() {
+    let input = convert_iqi32_to_complex(&[0x00, 0x00, 0x00, 0x80, 0x00, 0x00, 0x00, 0x40], Endianness::Little);
+    assert_eq!(input[0], Complex::<f32>::new(-1.0, 0.5));
+    assert_eq!(convert_iqi32_to_complex(&[0x80, 0x00, 0x00, 0x00, 0x40, 0x00, 0x00, 0x00], Endianness::Big), input);
+
+    let mut quantizer = Quantizer::new(false);
+    assert_eq!(convert_complex_to_iqi32(&input, Endianness::Little, &mut quantizer), [0x01, 0x00, 0x00, 0x80, 0x00, 0x00, 0x00, 0x40]);
+    assert_eq!(convert_complex_to_iqi32(&[Complex::<f32>::new(-2.0, 2.0)], Endianness::Big, &mut quantizer), [0x80, 0x00, 0x00, 0x00, 0x7F, 0xFF, 0xFF, 0xFF]);
+    assert_eq!(quantizer.clipped(), 1);
+}
+
+pub fn convert_iqf64_to_complex(inbuf: &[u8], endianness: Endianness) -> Vec<Complex<f32>> {
+    let mut output = Vec::<Complex<f32>>::new();
+    convert_iqf64_to_complex_into(inbuf, endianness, &mut output);
+    output
+}
+
+pub fn convert_iqf64_to_complex_into(inbuf: &[u8], endianness: Endianness, output: &mut Vec<Complex<f32>>) {
+    // inbuf consists of f64 IQ pairs that are represented as bytes here
+    assert!(inbuf.len().is_multiple_of(16));
+
+    output.clear();
+    output.reserve(inbuf.len()/16);
+
+    for b in inbuf.chunks(16) {
+        let i: f64 = f64::from_bits(read_uint(&b[0..8], endianness));
+        let q: f64 = f64::from_bits(read_uint(&b[8..16], endianness));
+
+        output.push(Complex::<f32>::new(i as f32, q as f32));
+    }
+}
+
+pub fn convert_complex_to_iqf64(inbuf: &[Complex<f32>], endianness: Endianness) -> Vec<u8> {
+    let mut output = Vec::<u8>::new();
+    convert_complex_to_iqf64_into(inbuf, endianness, &mut output);
+    output
+}
+
+pub fn convert_complex_to_iqf64_into(inbuf: &[Complex<f32>], endianness: Endianness, output: &mut Vec<u8>) {
+    output.clear();
+    output.reserve(inbuf.len() * 16);
+
+    for sample in inbuf {
+        push_uint(output, (sample.re as f64).to_bits(), 8, endianness);
+        push_uint(output, (sample.im as f64).to_bits(), 8, endianness);
+    }
+}
+
+#[test]
+fn test_convert_iqf64() {
+    let samples = [Complex::<f32>::new(-1.0, 0.25), Complex::<f32>::new(1e-3, -7.5)];
+
+    let bytes = convert_complex_to_iqf64(&samples, Endianness::Little);
+    assert_eq!(&bytes[0..8], &[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xF0, 0xBF]);
+    assert_eq!(convert_iqf64_to_complex(&bytes, Endianness::Little), samples);
+
+    let bytes = convert_complex_to_iqf64(&samples, Endianness::Big);
+    assert_eq!(&bytes[0..8], &[0xBF, 0xF0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    assert_eq!(convert_iqf64_to_complex(&bytes, Endianness::Big), samples);
+}
+
+/// Converts IQ bytes of any data type to complex samples.
+pub fn convert_to_complex(inbuf: &[u8], datatype: DataType, endianness: Endianness) -> Vec<Complex<f32>> {
+    let mut output = Vec::<Complex<f32>>::new();
+    convert_to_complex_into(inbuf, datatype, endianness, &mut output);
+    output
+}
+
+pub fn convert_to_complex_into(inbuf: &[u8], datatype: DataType, endianness: Endianness, output: &mut Vec<Complex<f32>>) {
+    match datatype {
+        DataType::I16 => convert_iqi16_to_complex_into(inbuf, endianness, output),
+        DataType::F32 => convert_iqf32_to_complex_into(inbuf, endianness, output),
+        DataType::U8 => convert_iqu8_to_complex_into(inbuf, output),
+        DataType::I8 => convert_iqi8_to_complex_into(inbuf, output),
+        DataType::I32 => convert_iqi32_to_complex_into(inbuf, endianness, output),
+        DataType::F64 => convert_iqf64_to_complex_into(inbuf, endianness, output),
+    }
+}
+
+/// Converts complex samples to IQ bytes of any data type.
+pub fn convert_from_complex(inbuf: &[Complex<f32>], datatype: DataType, endianness: Endianness, quantizer: &mut Quantizer) -> Vec<u8> {
+    let mut output = Vec::<u8>::new();
+    convert_from_complex_into(inbuf, datatype, endianness, quantizer, &mut output);
+    output
+}
+
+pub fn convert_from_complex_into(inbuf: &[Complex<f32>], datatype: DataType, endianness: Endianness, quantizer: &mut Quantizer, output: &mut Vec<u8>) {
+    match datatype {
+        DataType::I16 => convert_complex_to_iqi16_into(inbuf, endianness, quantizer, output),
+        DataType::F32 => convert_complex_to_iqf32_into(inbuf, endianness, output),
+        DataType::U8 => convert_complex_to_iqu8_into(inbuf, quantizer, output),
+        DataType::I8 => convert_complex_to_iqi8_into(inbuf, quantizer, output),
+        DataType::I32 => convert_complex_to_iqi32_into(inbuf, endianness, quantizer, output),
+        DataType::F64 => convert_complex_to_iqf64_into(inbuf, endianness, output),
+    }
+}
+
+// Phase of the oscillator is kept as a fraction of a full turn in 64 bit fixed point.
+// One full turn is 2^64, therefore phase wraps around by itself and never loses precision
+// no matter how long the stream is.
+const PHASE_TO_RAD: f64 = PI / 9223372036854775808_f64; // PI / 2^63
+const TURN: f64 = 18446744073709551616_f64; // 2^64
+
+/// Numerically controlled oscillator that is used for frequency shifting.
+///
+/// Changing the frequency only changes the phase increment, so the phase stays
+/// continuous across buffers even if the shift is updated between them.
+#[derive(Clone, Default)]
+pub struct Nco {
+    phase: u64,
+    increment: i64,
+    sweep: i64,
+}
+
+impl Nco {
+    pub fn new() -> Nco {
+        Nco {
+            phase: 0,
+            increment: 0,
+            sweep: 0,
         }
-        else {
-            *samplenum += 1;
+    }
+
+    fn increment(shift_hz: f64, samplerate: u32) -> i64 {
+        // keep increment in [-0.5, 0.5] turns, everything outside aliases anyway
+        let turns = shift_hz / samplerate as f64;
+        ((turns - turns.round()) * TURN) as i64
+    }
+
+    pub fn set_frequency(&mut self, shift_hz: f64, samplerate: u32) {
+        self.increment = Nco::increment(shift_hz, samplerate);
+        self.sweep = 0;
+    }
+
+    /// Sweeps frequency linearly from `start_hz` to `end_hz` during the next `samples` samples.
+    /// After that frequency keeps changing at the same rate until it is set again.
+    pub fn set_sweep(&mut self, start_hz: f64, end_hz: f64, samplerate: u32, samples: usize) {
+        let start = Nco::increment(start_hz, samplerate);
+        let end = Nco::increment(end_hz, samplerate);
+
+        self.sweep = if samples > 0 {end.wrapping_sub(start) / samples as i64} else {0};
+        // phase advance between samples is taken at the middle of the sample interval,
+        // this way phase accumulated during the sweep equals to the one of average frequency
+        self.increment = start.wrapping_add(self.sweep / 2);
+    }
+
+    /// Sets frequency to `shift_hz` that changes by `rate_hz_sec` every second until it is set again.
+    pub fn set_rate(&mut self, shift_hz: f64, rate_hz_sec: f64, samplerate: u32) {
+        let samplerate = samplerate as f64;
+        self.sweep = (rate_hz_sec / samplerate / samplerate * TURN) as i64;
+        // same midpoint rule as in set_sweep
+        self.increment = Nco::increment(shift_hz, samplerate as u32).wrapping_add(self.sweep / 2);
+    }
+
+    /// Current phase in radians, in range [-PI, PI).
+    pub fn phase(&self) -> f64 {
+        (self.phase as i64) as f64 * PHASE_TO_RAD
+    }
+
+    /// Advances the oscillator by `samples` without mixing anything,
+    /// state is exactly the same as after mixing that many samples.
+    pub fn skip(&mut self, samples: usize) {
+        // phase grows by n * increment + sweep * n * (n - 1) / 2, everything wraps modulo 2^64
+        let n = samples as u64;
+        let triangle = if n.is_multiple_of(2) {(n / 2).wrapping_mul(n.wrapping_sub(1))} else {n.wrapping_mul(n.wrapping_sub(1) / 2)};
+
+        self.phase = self.phase.wrapping_add(n.wrapping_mul(self.increment as u64))
+                               .wrapping_add(triangle.wrapping_mul(self.sweep as u64));
+        self.increment = self.increment.wrapping_add((n as i64).wrapping_mul(self.sweep));
+    }
+
+    fn step(&mut self) -> Complex<f32> {
+        let corrector = cexpf(Complex::<f32>::new(0.0, -self.phase() as f32));
+        self.phase = self.phase.wrapping_add(self.increment as u64);
+        self.increment = self.increment.wrapping_add(self.sweep);
+        corrector
+    }
+
+    pub fn mix(&mut self, inbuf: &[Complex<f32>]) -> Vec<Complex<f32>> {
+        let mut output = inbuf.to_vec();
+        self.mix_in_place(&mut output);
+        output
+    }
+
+    pub fn mix_in_place(&mut self, buf: &mut [Complex<f32>]) {
+        for sample in buf.iter_mut() {
+            *sample *= self.step();
         }
     }
+}
 
-    output
+pub fn shift_frequency(inbuf: &[Complex<f32>], nco: &mut Nco, shift_hz: f64, samplerate: u32) -> Vec<Complex<f32>> {
+    nco.set_frequency(shift_hz, samplerate);
+    nco.mix(inbuf)
+}
+
+pub fn shift_frequency_in_place(buf: &mut [Complex<f32>], nco: &mut Nco, shift_hz: f64, samplerate: u32) {
+    nco.set_frequency(shift_hz, samplerate);
+    nco.mix_in_place(buf);
+}
+
+pub fn sweep_frequency(inbuf: &[Complex<f32>], nco: &mut Nco, start_hz: f64, end_hz: f64, samplerate: u32) -> Vec<Complex<f32>> {
+    nco.set_sweep(start_hz, end_hz, samplerate, inbuf.len());
+    nco.mix(inbuf)
+}
+
+pub fn sweep_frequency_in_place(buf: &mut [Complex<f32>], nco: &mut Nco, start_hz: f64, end_hz: f64, samplerate: u32) {
+    nco.set_sweep(start_hz, end_hz, samplerate, buf.len());
+    nco.mix_in_place(buf);
+}
+
+#[test]
+fn test_nco_phase_continuity() {
+    let samplerate: u32 = 8;
+    let mut nco = Nco::new();
+
+    // 1 Hz at 8 sps is 1/8 turn per sample, after 4 samples phase is PI/2 * 2 = -PI (wrapped)
+    nco.set_frequency(1.0, samplerate);
+    nco.mix(&[Complex::<f32>::new(1.0, 0.0); 4]);
+    assert!((nco.phase().abs() - PI).abs() < 1e-12);
+
+    // changing frequency must not make phase jump, it continues from where it was
+    nco.set_frequency(-2.0, samplerate);
+    assert!((nco.phase().abs() - PI).abs() < 1e-12);
+    nco.mix(&[Complex::<f32>::new(1.0, 0.0); 2]);
+    assert!(nco.phase().abs() < 1e-12);
+}
+
+#[test]
+fn test_nco_sweep() {
+    let samplerate: u32 = 1000;
+    let mut nco = Nco::new();
+
+    // sweeping from 0 to 100 Hz during 1000 samples accumulates 50 turns on average frequency of 50 Hz
+    nco.set_sweep(0.0, 100.0, samplerate, 1000);
+    nco.mix(&[Complex::<f32>::new(1.0, 0.0); 1000]);
+    assert!(nco.phase().abs() < 1e-6);
+
+    // at the end of the sweep it continues on end frequency, 100 Hz during 5 ms is half a turn
+    nco.set_frequency(100.0, samplerate);
+    nco.mix(&[Complex::<f32>::new(1.0, 0.0); 5]);
+    assert!((nco.phase().abs() - PI).abs() < 1e-6);
+}
+
+#[test]
+fn test_nco_skip() {
+    let samplerate: u32 = 48000;
+    let mut mixed = Nco::new();
+    let mut skipped = Nco::new();
+
+    for &(start_hz, end_hz, samples) in &[(1000.0, 1200.0, 1001), (-20000.0, 23000.0, 4096), (5.0, 5.0, 0), (15000.0, -3000.0, 7)] {
+        mixed.set_sweep(start_hz, end_hz, samplerate, samples);
+        skipped.set_sweep(start_hz, end_hz, samplerate, samples);
+
+        mixed.mix(&vec![Complex::<f32>::new(1.0, 0.0); samples]);
+        skipped.skip(samples);
+
+        assert_eq!(mixed.phase, skipped.phase);
+        assert_eq!(mixed.increment, skipped.increment);
+    }
+}
+
+#[test]
+fn test_sweep_frequency_in_place() {
+    let samplerate: u32 = 48000;
+    let input = convert_iqi16_to_complex(&[0x12, 0x34, 0x56, 0x78].repeat(100), Endianness::Little);
+
+    let mut nco = Nco::new();
+    let output = sweep_frequency(&input, &mut nco, 1000.0, 2000.0, samplerate);
+
+    let mut nco = Nco::new();
+    let mut buf = Vec::new();
+    convert_iqi16_to_complex_into(&[0x12, 0x34, 0x56, 0x78].repeat(100), Endianness::Little, &mut buf);
+    sweep_frequency_in_place(&mut buf, &mut nco, 1000.0, 2000.0, samplerate);
+    assert_eq!(buf, output);
+
+    // buffer is reused, old content is cleared
+    let mut bytes = vec![0xFF; 3];
+    convert_complex_to_iqu8_into(&buf[..1], &mut Quantizer::new(false), &mut bytes);
+    assert_eq!(bytes.len(), 2);
 }
 
 #[test]
@@ -138,16 +723,16 @@ fn test_bench_shift_frequency() {
     // use as:
     // cargo test test_bench_shift_frequency -- release
 
-    let mut samplenr: u32 = 0;
-    let shift_hz: f32 = 815000.0;
+    let mut nco = Nco::new();
+    let shift_hz: f64 = 815000.0;
     let samplerate: u32 = 2400000;
 
     let input: [u8; 1_000_000] = [0xAA; 1_000_000];
-    let complex_input = convert_iqf32_to_complex(&input);
+    let complex_input = convert_iqf32_to_complex(&input, Endianness::Little);
 
     let mut iterator = 0;
     loop {
-        shift_frequency(&complex_input, &mut samplenr, shift_hz, samplerate);
+        shift_frequency(&complex_input, &mut nco, shift_hz, samplerate);
 
         iterator += 1;
         if iterator > 300 {
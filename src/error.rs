@@ -0,0 +1,95 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::fmt;
+use std::io;
+
+/// Errors that stop processing. Each kind has its own exit code.
+#[derive(Debug)]
+pub enum Error {
+    /// Invalid or missing command line argument.
+    Argument(String),
+    /// TLE file can't be read or satellite is not found from it.
+    Tle(String),
+    /// Reading input or writing output failed.
+    Io(String, io::ErrorKind),
+    /// Recording or metadata has invalid or unsupported format.
+    Format(String),
+    /// Satellite position can't be predicted from TLE.
+    Prediction(String),
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+impl Error {
+    /// Error from an I/O operation, `message` describes what failed.
+    pub fn io(message: String, error: &io::Error) -> Error {
+        Error::Io(message, error.kind())
+    }
+
+    /// Process exit code, broken pipe gets the same code as a process killed by SIGPIPE.
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            Error::Argument(_) => 2,
+            Error::Tle(_) => 3,
+            Error::Io(_, io::ErrorKind::BrokenPipe) => 141,
+            Error::Io(..) => 4,
+            Error::Format(_) => 5,
+            Error::Prediction(_) => 6,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Argument(ref message) |
+            Error::Tle(ref message) |
+            Error::Io(ref message, _) |
+            Error::Format(ref message) |
+            Error::Prediction(ref message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl ::std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io(error.to_string(), error.kind())
+    }
+}
+
+#[test]
+fn test_exit_code() {
+    assert_eq!(Error::Tle("ESTCUBE 1 not found in cubesat.txt".to_string()).exit_code(), 3);
+    assert_eq!(Error::io("can't write output".to_string(), &io::Error::new(io::ErrorKind::BrokenPipe, "pipe")).exit_code(), 141);
+    assert_eq!(Error::Io("can't open input".to_string(), io::ErrorKind::NotFound).exit_code(), 4);
+    assert_eq!(Error::from(io::Error::new(io::ErrorKind::BrokenPipe, "pipe")).exit_code(), 141);
+
+    // can be returned from functions that return other errors too
+    use std::error::Error as StdError;
+    let boxed: Box<dyn StdError> = Box::new(Error::Format("not a WAV file".to_string()));
+    assert_eq!(boxed.to_string(), "not a WAV file");
+}
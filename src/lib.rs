@@ -25,11 +25,25 @@
 extern crate libc;
 extern crate time;
 extern crate num;
+extern crate serde_json;
 
 #[macro_use] extern crate log;
-#[macro_use] extern crate clap;
+extern crate clap;
 extern crate gpredict;
-extern crate liquid_dsp;
 
+pub mod error;
 pub mod usage;
 pub mod dsp;
+pub mod clock;
+pub mod sigmf;
+pub mod wav;
+pub mod recording;
+pub mod stream;
+pub mod mmap;
+pub mod parallel;
+pub mod signal;
+pub mod prediction;
+pub mod corrector;
+
+pub use corrector::DopplerCorrector;
+pub use error::Error;
@@ -26,186 +26,686 @@
 // import local modules
 extern crate doppler;
 use doppler::usage;
-use doppler::usage::Mode::{ConstMode, TrackMode};
-use doppler::usage::DataType::{I16, F32};
+use doppler::Error;
+use doppler::usage::{CommandArgs, DataType, Endianness};
+use doppler::usage::{PredictFormat};
+use doppler::usage::Mode::{ConstMode, TrackMode, BatchMode, PredictMode};
 use doppler::dsp;
+use doppler::clock::format_time;
+use doppler::sigmf;
+use doppler::wav;
+use doppler::stream::{Source, Sink, BlockReader};
+use doppler::mmap::Mmap;
+use doppler::parallel;
+use doppler::signal;
+use doppler::corrector;
+use doppler::corrector::{DopplerCorrector, Sweep, Update, Tle, Location};
+use doppler::prediction;
+use doppler::prediction::{Prediction, Predictor};
 
 // import external modules
 #[macro_use]
 extern crate log;
 extern crate fern;
 use std::process::exit;
-use std::io;
 use std::io::prelude::*;
-use std::io::BufReader;
+use std::io;
 use std::io::BufWriter;
-use std::slice;
+use std::fmt;
+use std::fs::File;
+use std::thread;
+use std::cmp;
 
 extern crate time;
-extern crate gpredict;
-use gpredict::{Predict, Tle, Location};
+extern crate num;
+use num::complex::Complex;
+
+const DEFAULT_BLOCK_SAMPLES: usize = 2048;
+// number of samples that each thread corrects at once in parallel mode
+const PARALLEL_SAMPLES: usize = 1 << 20;
+
+/// Why processing stopped before the end of input.
+#[derive(Clone, Copy)]
+enum Stop {
+    Signal(i32),
+    BrokenPipe,
+}
 
-const SPEED_OF_LIGHT_M_S: f64 = 299792458.;
-const BUFFER_SIZE: usize = 8192;
+impl Stop {
+    /// Same exit code as the shell gives to a process that is killed by the signal.
+    fn exit_code(&self) -> i32 {
+        match *self {
+            Stop::Signal(signal) => 128 + signal,
+            Stop::BrokenPipe => Error::Io(String::new(), io::ErrorKind::BrokenPipe).exit_code(),
+        }
+    }
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Stop::Signal(signal) => write!(f, "signal {}", signal),
+            Stop::BrokenPipe => write!(f, "output pipe was closed"),
+        }
+    }
+}
+
+/// Totals of one processed input.
+struct Summary {
+    samples: u64,
+    samplerate: u32,
+    clipped: u64,
+    // smallest and largest predicted doppler in track mode
+    doppler: Option<(f64, f64)>,
+    stop: Option<Stop>,
+}
+
+impl Summary {
+    fn duration(&self) -> f64 {
+        self.samples as f64 / self.samplerate as f64
+    }
+
+    fn log(&self) {
+        info!("summary");
+        info!("\tsamples         : {}", self.samples);
+        info!("\tduration        : {:.1} s", self.duration());
+        if let Some((min, max)) = self.doppler {
+            info!("\tdoppler         : {:.2} .. {:.2} Hz", min, max);
+        }
+        info!("\tclipped samples : {}", self.clipped);
+        if let Some(ref stop) = self.stop {
+            info!("\tstopped by      : {}", stop);
+        }
+    }
+}
 
 fn main() {
     setup_logger();
-    let args = usage::args();
+    signal::install();
+    let args = usage::args().unwrap_or_else(|e| {
+        error!("{}.", e);
+        exit(e.exit_code());
+    });
 
     info!("doppler {} andres.vahter@gmail.com\n\n", env!("CARGO_PKG_VERSION"));
 
-    let mut stdin = BufReader::with_capacity(BUFFER_SIZE*2, io::stdin());
-    let mut stdout = BufWriter::new(io::stdout());
+    match *args.mode.as_ref().unwrap() {
+        BatchMode => batch(&args),
+        PredictMode => {
+            match predict(&args) {
+                Ok(Some(stop)) => {
+                    warn!("stopped by {}", stop);
+                    exit(stop.exit_code());
+                }
+                Ok(None) => {},
+                Err(e) => {
+                    error!("{}", e);
+                    exit(e.exit_code());
+                }
+            }
+        }
+        _ => {
+            match process(&args) {
+                Ok(Summary{stop: Some(stop), ..}) => exit(stop.exit_code()),
+                Ok(_) => {},
+                Err(e) => {
+                    error!("{}", e);
+                    exit(e.exit_code());
+                }
+            }
+        }
+    }
+}
 
-    let mut samplenr: u32 = 0;
+fn batch(args: &CommandArgs) {
+    info!("batch mode");
+    info!("\trecordings      : {}", args.batchargs.files.len());
+    info!("\tTLE file        : {}", args.trackargs.tlefile.as_ref().unwrap());
+    info!("\tTLE name        : {}", args.trackargs.tlename.as_ref().unwrap());
+    info!("\tlocation        : {:?}\n\n", args.trackargs.location.as_ref().unwrap());
 
-    let mut shift = |intype: doppler::usage::DataType, shift_hz: f32, samplerate: u32| {
-        let invec = stdin.by_ref().bytes().take(BUFFER_SIZE).collect::<Result<Vec<u8>,_>>().ok().expect("doppler collect error");
+    let mut results = vec![];
 
-        let input = match intype {
-                I16 => dsp::convert_iqi16_to_complex(&invec),
-                F32 => dsp::convert_iqf32_to_complex(&invec),
-        };
+    for file in &args.batchargs.files {
+        info!("processing {}", file);
 
-        let output = dsp::shift_frequency(&input, &mut samplenr, shift_hz, samplerate);
+        let result = usage::recording_args(args, file).and_then(|a| {
+            process(&a).map(|summary| (a.output.clone(), summary))
+        });
 
-        match *args.outputtype.as_ref().unwrap() {
-            doppler::usage::DataType::I16 => {
-                let mut outputi16 = Vec::<u8>::with_capacity(output.len() * 4);
+        if let Err(ref e) = result {
+            error!("{}: {}", file, e);
+        }
 
-                for sample in &output[..] {
-                    let i = (sample.re * 32767.0) as i16;
-                    let q = (sample.im * 32767.0) as i16;
+        // rest of the recordings are skipped if interrupted
+        let stop = result.as_ref().ok().and_then(|r| r.1.stop);
+        results.push((file, result));
 
-                    outputi16.push((i & 0xFF) as u8);
-                    outputi16.push(((i >> 8) & 0xFF) as u8);
-                    outputi16.push((q & 0xFF) as u8);
-                    outputi16.push(((q >> 8) & 0xFF) as u8);
-                }
+        if stop.is_some() {
+            break;
+        }
+    }
 
-                stdout.write(&outputi16[..]).map_err(|e|{info!("doppler stdout.write error: {:?}", e)}).unwrap();
+    info!("batch summary");
+    let mut failed = 0;
+    // exit code tells the kind of the first failure
+    let mut exit_code = 0;
+    let mut stop = None;
+    for (file, result) in results {
+        match result {
+            Ok((output, summary)) => {
+                info!("\t{} -> {} : {:.1} s, {} clipped samples", file, output, summary.duration(), summary.clipped);
+                stop = summary.stop;
             },
+            Err(e) => {
+                error!("\t{} : {}", file, e);
+                if failed == 0 {
+                    exit_code = e.exit_code();
+                }
+                failed += 1;
+            }
+        }
+    }
 
-            doppler::usage::DataType::F32 => {
-                // * 8 because Complex<f32> is 8 bytes long
-                let slice = unsafe {slice::from_raw_parts(output.as_ptr() as *const _, output.len() * 8)};
-                stdout.write(&slice).map_err(|e|{info!("doppler stdout.write error: {:?}", e)}).unwrap();
-            },
-        };
+    if failed > 0 {
+        error!("{} of {} recordings failed", failed, args.batchargs.files.len());
+        exit(exit_code);
+    }
+
+    if let Some(stop) = stop {
+        warn!("stopped by {}, remaining recordings were skipped", stop);
+        exit(stop.exit_code());
+    }
+}
+
+/// Reads input blocks into complex samples and writes them out after correction.
+/// Buffers are allocated once and reused for every block.
+struct BlockIo {
+    reader: BlockReader<Source>,
+    sink: BufWriter<Sink>,
+    inbuf: Vec<u8>,
+    samples: Vec<Complex<f32>>,
+    outbuf: Vec<u8>,
+    intype: DataType,
+    inendianness: Endianness,
+    outtype: DataType,
+    outendianness: Endianness,
+    gain: f32,
+    quantizer: dsp::Quantizer,
+}
+
+impl BlockIo {
+    /// Reads next block to samples, returns false if input ended with this block.
+    fn read(&mut self) -> Result<bool, Error> {
+        let count = self.reader.read_block(&mut self.inbuf).map_err(|e| Error::io(format!("can't read input: {}", e), &e))?;
+        dsp::convert_to_complex_into(&self.inbuf[..count], self.intype, self.inendianness, &mut self.samples);
+
+        if count != self.inbuf.len() {
+            report_truncated(self.reader.truncated());
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+
+    /// Writes samples to output, returns false if output pipe was closed.
+    fn write(&mut self) -> Result<bool, Error> {
+        if self.gain != 0.0 {
+            dsp::apply_gain(&mut self.samples, self.gain);
+        }
+
+        dsp::convert_from_complex_into(&self.samples, self.outtype, self.outendianness, &mut self.quantizer, &mut self.outbuf);
+
+        output(&mut self.sink, &self.outbuf)
+    }
+}
+
+/// Writes bytes to output and flushes it, returns false if reader has closed the pipe.
+fn output(sink: &mut BufWriter<Sink>, bytes: &[u8]) -> Result<bool, Error> {
+    match sink.write_all(bytes).and_then(|_| sink.flush()) {
+        Ok(()) => Ok(true),
+        Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(false),
+        Err(e) => Err(Error::io(format!("can't write output: {}", e), &e)),
+    }
+}
+
+/// Runs const or track mode on input given in args.
+fn process(args: &CommandArgs) -> Result<Summary, Error> {
+    let source = Source::open(&args.input, args.inputoffset, args.inputlength)?;
+    let sink = Sink::create(&args.output)?;
 
+    let threads = args.threads.unwrap_or_else(|| thread::available_parallelism().map(|n| n.get()).unwrap_or(1));
 
-        stdout.flush().map_err(|e|{info!("doppler stdout.flush error: {:?}", e)}).unwrap();
-        (invec.len() != BUFFER_SIZE, output.len())
+    // recordings with known start time are corrected in parallel straight from memory mapped file,
+    // doppler of every block is known ahead because time comes from the sample index
+    let recording = match args.mode {
+        Some(TrackMode) => args.trackargs.time.is_some(),
+        _ => false,
     };
 
-    match *args.mode.as_ref().unwrap() {
+    // recordings that don't fit into address space are streamed instead
+    let mapping = match source {
+        Source::File{ref path, offset, length, ..} if recording && threads > 1 => Mmap::open(path, offset, length)?,
+        _ => None,
+    };
+
+    // used for showing progress
+    let inputlength = source.length();
+
+    let mut sink = BufWriter::new(sink);
+
+    if args.trackargs.outputwav.is_some() {
+        // sizes are fixed when output is finished
+        wav::write_header(&mut sink, args.outputtype.unwrap(), args.samplerate.unwrap(), None)?;
+    }
+
+    let intype = args.inputtype.unwrap();
+    let outtype = args.outputtype.unwrap();
+    let outendianness = args.outputendianness.unwrap();
+    let samplerate = args.samplerate.unwrap();
+    let block_samples = args.blocksamples.unwrap_or(DEFAULT_BLOCK_SAMPLES);
+
+    let mut io = BlockIo {
+        reader: BlockReader::new(source, intype.sample_size()),
+        sink,
+        inbuf: vec![0; block_samples * intype.sample_size()],
+        samples: Vec::with_capacity(block_samples),
+        outbuf: Vec::with_capacity(block_samples * outtype.sample_size()),
+        intype,
+        inendianness: args.inputendianness.unwrap(),
+        outtype,
+        outendianness,
+        gain: args.gain.unwrap_or(0.0),
+        quantizer: dsp::Quantizer::new(args.dither),
+    };
+
+    let mut nco = dsp::Nco::new();
+
+    let summary = match *args.mode.as_ref().unwrap() {
         ConstMode => {
             info!("constant shift mode");
+            info!("\tIQ input        : {}", args.input);
+            info!("\tIQ output       : {}", args.output);
             info!("\tIQ samplerate   : {}", args.samplerate.as_ref().unwrap());
-            info!("\tIQ input type   : {}", args.inputtype.as_ref().unwrap());
-            info!("\tIQ output type  : {}\n", args.outputtype.as_ref().unwrap());
+            info!("\tIQ input type   : {} {}", args.inputtype.as_ref().unwrap(), args.inputendianness.as_ref().unwrap());
+            info!("\tIQ output type  : {} {}\n", args.outputtype.as_ref().unwrap(), args.outputendianness.as_ref().unwrap());
+            info!("\tblock samples   : {}", block_samples);
             info!("\tfrequency shift : {} Hz", args.constargs.shift.as_ref().unwrap());
+            info!("\tgain            : {} dB", args.gain.unwrap_or(0.0));
+            info!("\tdither          : {}", args.dither);
+
+            nco.set_frequency(args.constargs.shift.unwrap() as f64, samplerate);
+            let mut samples: u64 = 0;
+            let stop = loop {
+                let more = io.read()?;
+                nco.mix_in_place(&mut io.samples);
+                if !io.write()? {
+                    break Some(Stop::BrokenPipe);
+                }
 
-            let intype = args.inputtype.unwrap();
-            let shift_hz = args.constargs.shift.unwrap() as f32;
-            let samplerate = args.samplerate.unwrap();
-
-            loop {
-                let stop_and_count: (bool, usize) = shift(intype, shift_hz, samplerate);
-                if stop_and_count.0 {
-                    break;
+                samples += io.samples.len() as u64;
+                let stop = signal::received().map(Stop::Signal);
+                if !more || stop.is_some() {
+                    break stop;
                 }
-            }
+            };
+
+            report_clipped(io.quantizer.clipped());
+            Summary{samples, samplerate, clipped: io.quantizer.clipped(), doppler: None, stop}
         }
 
 
         TrackMode => {
+            let update_rate = args.trackargs.updaterate.unwrap_or(corrector::DEFAULT_UPDATE_RATE);
+
             info!("tracking mode");
+            info!("\tIQ input        : {}", args.input);
+            info!("\tIQ output       : {}", args.output);
             info!("\tIQ samplerate   : {}", args.samplerate.as_ref().unwrap());
-            info!("\tIQ input type   : {}", args.inputtype.as_ref().unwrap());
-            info!("\tIQ output type  : {}\n", args.outputtype.as_ref().unwrap());
+            info!("\tIQ input type   : {} {}", args.inputtype.as_ref().unwrap(), args.inputendianness.as_ref().unwrap());
+            info!("\tIQ output type  : {} {}\n", args.outputtype.as_ref().unwrap(), args.outputendianness.as_ref().unwrap());
+            info!("\tblock samples   : {}", block_samples);
             info!("\tTLE file        : {}", args.trackargs.tlefile.as_ref().unwrap());
             info!("\tTLE name        : {}", args.trackargs.tlename.as_ref().unwrap());
             info!("\tlocation        : {:?}", args.trackargs.location.as_ref().unwrap());
-            if args.trackargs.time.is_some() {
-                info!("\ttime            : {:.3}", args.trackargs.time.unwrap().to_utc().rfc3339());
+            if let Some(ref time) = args.trackargs.time {
+                info!("\ttime            : {}", format_time(time));
             }
             info!("\tfrequency       : {} Hz", args.trackargs.frequency.as_ref().unwrap());
-            info!("\toffset          : {} Hz\n\n\n", args.trackargs.offset.unwrap_or(0));
+            info!("\toffset          : {} Hz", args.trackargs.offset.unwrap_or(0));
+            info!("\tdoppler update  : {} Hz", update_rate);
+            info!("\tchirp           : {}", args.trackargs.chirp);
+            info!("\tdoppler rate    : {}", args.trackargs.dopplerrate);
+            info!("\tgain            : {} dB", args.gain.unwrap_or(0.0));
+            info!("\tdither          : {}", args.dither);
+            if mapping.is_some() {
+                info!("\tthreads         : {}", threads);
+            }
+            if let Some(ref basename) = args.trackargs.outputsigmf {
+                info!("\tSigMF output    : {}", basename);
+            }
+            if let Some(ref path) = args.trackargs.outputwav {
+                info!("\tWAV output      : {}", path);
+            }
+            info!("\n\n");
 
             let l = args.trackargs.location.unwrap();
             let location: Location = Location{lat_deg: l.lat, lon_deg: l.lon, alt_m: l.alt};
             let tlename = args.trackargs.tlename.as_ref().unwrap();
             let tlefile = args.trackargs.tlefile.as_ref().unwrap();
 
-            let tle = match Tle::from_file(&tlename, &tlefile) {
-                Ok(t) => {t},
-                Err(e) => {
-                    info!("{}", e);
-                    exit(1);
-                }
+            let tle = Tle::from_file(tlename, tlefile).map_err(|e| Error::Tle(format!("can't read {} from {}: {}", tlename, tlefile, e)))?;
+
+            let frequency = args.trackargs.frequency.unwrap();
+            let offset = args.trackargs.offset.unwrap_or(0) as f64;
+            let start_time = args.trackargs.time.unwrap_or(time::now_utc());
+
+            let mut corrector = match args.trackargs.time {
+                Some(time) => DopplerCorrector::new(&tle, &location, frequency as f64, samplerate, time),
+                None => DopplerCorrector::realtime(&tle, &location, frequency as f64, samplerate),
             };
+            corrector.set_offset(offset);
+            corrector.set_update_rate(update_rate);
+            corrector.set_sweep(if args.trackargs.dopplerrate {
+                Sweep::Rate
+            }
+            else if args.trackargs.chirp {
+                Sweep::Chirp
+            }
+            else {
+                Sweep::Step
+            });
+
+            // receiver tracking loops must be able to follow the largest doppler rate of the pass
+            match Predictor::new(&tle, &location, frequency as f64).max_doppler_rate(start_time) {
+                Some(max) => info!("max doppler rate    : {:.2} Hz/sec at {}, el {:.2}°\n", max.doppler_rate_hz_sec, format_time(&max.time), max.el_deg),
+                None => warn!("{} does not rise above horizon within a day", tlename),
+            }
 
-            let mut predict: Predict = Predict::new(&tle, &location);
-            let intype = args.inputtype.unwrap();
-
-            let samplerate = args.samplerate.unwrap();
-            let mut last_time: time::Tm = time::now_utc();
-
-            match args.trackargs.time {
-                Some(start_time) => {
-                    let mut sample_count = 0;
-                    let mut dt = time::Duration::seconds(0);
-                    last_time = start_time;
-
-                    loop {
-                        predict.update(Some(start_time + dt));
-                        let doppler_hz = (predict.sat.range_rate_km_sec * 1000_f64 / SPEED_OF_LIGHT_M_S) * args.trackargs.frequency.unwrap() as f64 * (-1.0);
-
-                        // advance time based on how many samples are read in
-                        dt = time::Duration::seconds((sample_count as f32 / samplerate as f32) as i64);
-                        if start_time + dt - last_time >= time::Duration::seconds(5) {
-                            last_time = start_time + dt;
-                            info!("time                : {:}", (start_time + dt).to_utc().rfc3339());
-                            info!("az                  : {:.2}°", predict.sat.az_deg);
-                            info!("el                  : {:.2}°", predict.sat.el_deg);
-                            info!("range               : {:.0} km", predict.sat.range_km);
-                            info!("range rate          : {:.3} km/sec", predict.sat.range_rate_km_sec);
-                            info!("doppler@{:.3} MHz : {:.2} Hz\n", args.trackargs.frequency.unwrap() as f32 / 1000_000_f32, doppler_hz);
-                        }
+            let mut samples: u64 = 0;
+            let mut clipped: u64 = 0;
+            let mut stop = None;
+            let mut doppler: Option<(f64, f64)> = None;
+            let mut events = PassEvents::new();
+
+            // status is logged from doppler updates, recordings are processed faster than realtime
+            let status_interval = time::Duration::seconds(if args.trackargs.time.is_some() {5} else {1});
+            let mut last_time: time::Tm = start_time;
 
-                        let (stop, count): (bool, usize) = shift(intype, doppler_hz as f32 + args.trackargs.offset.unwrap_or(0) as f32, samplerate);
-                        if stop {
-                            break;
+            let mut report = |updates: &[Update], clipped: u64| -> Result<(), Error> {
+                for update in updates {
+                    let prediction = &update.prediction;
+
+                    // SGP4 gives NaN for example if TLE is too old and satellite has decayed
+                    if !prediction.doppler_hz.is_finite() {
+                        return Err(Error::Prediction(format!("can't predict doppler at {}, check if TLE is up to date", format_time(&prediction.time))));
+                    }
+
+                    if prediction.time - last_time >= status_interval {
+                        last_time = prediction.time;
+                        log_status(prediction, frequency);
+                        if let Some(length) = inputlength {
+                            let processed = update.sample * intype.sample_size() as u64;
+                            info!("progress            : {:.1} %", processed as f64 / length.max(1) as f64 * 100.0);
                         }
+                        info!("clipped samples     : {}\n", clipped);
+                    }
+
+                    doppler = Some(match doppler {
+                        Some((min, max)) => (min.min(prediction.doppler_hz), max.max(prediction.doppler_hz)),
+                        None => (prediction.doppler_hz, prediction.doppler_hz),
+                    });
+
+                    events.update(update);
+                }
+
+                Ok(())
+            };
+
+            if let Some(ref mapping) = mapping {
+                let input = mapping.as_slice();
+                let total = (input.len() / intype.sample_size()) as u64;
+                report_truncated(input.len() % intype.sample_size());
+
+                let format = parallel::Format {
+                    intype,
+                    inendianness: io.inendianness,
+                    outtype,
+                    outendianness,
+                    gain: io.gain,
+                    dither: args.dither,
+                };
+
+                // doppler of a window is predicted ahead and its blocks are corrected on all threads
+                while corrector.samples() < total {
+                    stop = signal::received().map(Stop::Signal);
+                    if stop.is_some() {
+                        break;
+                    }
+
+                    let window = cmp::min(total - corrector.samples(), (threads * PARALLEL_SAMPLES) as u64);
+                    let blocks = corrector.schedule(window as usize);
+                    report(corrector.updates(), clipped)?;
 
-                        sample_count += count;
+                    let (bytes, clipped_count) = parallel::process(input, &blocks, &format, threads);
+                    if !output(&mut io.sink, &bytes)? {
+                        stop = Some(Stop::BrokenPipe);
+                        break;
                     }
+
+                    samples += window;
+                    clipped += clipped_count;
                 }
+            }
+            else {
+                loop {
+                    let more = io.read()?;
+                    corrector.process_in_place(&mut io.samples);
+                    report(corrector.updates(), clipped)?;
+
+                    if !io.write()? {
+                        stop = Some(Stop::BrokenPipe);
+                        break;
+                    }
 
-                None => {
-                    loop {
-                        predict.update(None);
-                        let doppler_hz = (predict.sat.range_rate_km_sec * 1000_f64 / SPEED_OF_LIGHT_M_S) * args.trackargs.frequency.unwrap() as f64 * (-1.0);
-
-                        if time::now_utc() - last_time >= time::Duration::seconds(1) {
-                            last_time = time::now_utc();
-                            info!("time                : {:}", time::now_utc().to_utc().rfc3339());
-                            info!("az                  : {:.2}°", predict.sat.az_deg);
-                            info!("el                  : {:.2}°", predict.sat.el_deg);
-                            info!("range               : {:.0} km", predict.sat.range_km);
-                            info!("range rate          : {:.3} km/sec", predict.sat.range_rate_km_sec);
-                            info!("doppler@{:.3} MHz : {:.2} Hz\n", args.trackargs.frequency.unwrap() as f32 / 1000_000_f32, doppler_hz);
-                        }
+                    samples += io.samples.len() as u64;
+                    clipped = io.quantizer.clipped();
 
-                        let (stop, _): (bool, usize) = shift(intype, doppler_hz as f32 + args.trackargs.offset.unwrap_or(0) as f32, samplerate);
-                        if stop {
-                            break;
-                        }
+                    stop = signal::received().map(Stop::Signal);
+                    if !more || stop.is_some() {
+                        break;
                     }
                 }
+            }
+
+            report_clipped(clipped);
+
+            if let Some(ref path) = args.trackargs.outputwav {
+                io.sink.flush().map_err(|e| Error::io(format!("can't write output: {}", e), &e))?;
+
+                // streamed output keeps unknown sizes in the header, it can't be seeked back to
+                if io.sink.get_ref().is_regular_file() {
+                    wav::finalize(path)?;
+                }
+            }
+
+            if let Some(ref basename) = args.trackargs.outputsigmf {
+                let tle = read_tle_lines(tlefile, tlename).unwrap_or_else(|e| {
+                    warn!("{}", e);
+                    vec![]
+                });
+
+                // after correction signal on the satellite frequency is moved to where it would be without doppler,
+                // if capture frequency is not known it is assumed that offset compensates receiver tuning
+                let capture_frequency = args.trackargs.capturefrequency.unwrap_or(frequency as f64 - offset);
+
+                let meta = sigmf::OutputMeta {
+                    datatype: outtype,
+                    endianness: outendianness,
+                    samplerate,
+                    frequency: capture_frequency + offset,
+                    datetime: start_time,
+                    tle,
+                    location: l,
+                    offset: args.trackargs.offset.unwrap_or(0),
+                    annotations: events.annotations,
+                };
+
+                let metafile = format!("{}{}", basename, sigmf::META_EXTENSION);
+                meta.write(&metafile)?;
+            }
+
+            Summary{samples, samplerate, clipped, doppler, stop}
+        }
+
+        BatchMode | PredictMode => unreachable!(),
+    };
+
+    summary.log();
+    Ok(summary)
+}
+
+/// Finds AOS, TCA and LOS moments from consecutive predictions for SigMF annotations.
+struct PassEvents {
+    last: Option<(f64, f64)>,
+    annotations: Vec<sigmf::Annotation>,
+}
+
+impl PassEvents {
+    fn new() -> PassEvents {
+        PassEvents {
+            last: None,
+            annotations: vec![],
+        }
+    }
+
+    fn update(&mut self, update: &Update) {
+        let prediction = &update.prediction;
+        let el = prediction.el_deg;
+        let range_rate = prediction.range_rate_km_sec;
+
+        if let Some((last_el, last_range_rate)) = self.last {
+            let mut add = |label: &str| {
+                info!("{} at {}", label, format_time(&prediction.time));
+                self.annotations.push(sigmf::Annotation {
+                    sample_start: update.sample,
+                    label: label.to_string(),
+                    comment: format!("{} az {:.2} el {:.2}", format_time(&prediction.time), prediction.az_deg, el),
+                });
             };
+
+            if last_el < 0.0 && el >= 0.0 {
+                add("AOS");
+            }
+
+            // satellite is closest when range stops decreasing
+            if last_range_rate < 0.0 && range_rate >= 0.0 && el >= 0.0 {
+                add("TCA");
+            }
+
+            if last_el >= 0.0 && el < 0.0 {
+                add("LOS");
+            }
+        }
+
+        self.last = Some((el, range_rate));
+    }
+}
+
+/// Writes doppler curve predicted for the time span given in args.
+/// Returns why it stopped if it was interrupted before the end of the time span.
+fn predict(args: &CommandArgs) -> Result<Option<Stop>, Error> {
+    let l = args.trackargs.location.unwrap();
+    let location: Location = Location{lat_deg: l.lat, lon_deg: l.lon, alt_m: l.alt};
+    let tlename = args.trackargs.tlename.as_ref().unwrap();
+    let tlefile = args.trackargs.tlefile.as_ref().unwrap();
+    let frequency = args.trackargs.frequency.unwrap();
+    let start_time = args.trackargs.time.unwrap_or(time::now_utc());
+    let duration = args.predictargs.duration;
+    let step = args.predictargs.step;
+
+    info!("predict mode");
+    info!("\tTLE file        : {}", tlefile);
+    info!("\tTLE name        : {}", tlename);
+    info!("\tlocation        : {:?}", l);
+    info!("\tfrequency       : {:.3} MHz", frequency as f32 / 1_000_000_f32);
+    info!("\tstart time      : {}", format_time(&start_time));
+    info!("\tduration        : {} sec", duration);
+    info!("\tstep            : {} sec", step);
+    info!("\toutput          : {}\n\n", args.output);
+
+    let tle = Tle::from_file(tlename, tlefile).map_err(|e| Error::Tle(format!("can't read {} from {}: {}", tlename, tlefile, e)))?;
+    let mut predictor = Predictor::new(&tle, &location, frequency as f64);
+    let mut sink = BufWriter::new(Sink::create(&args.output)?);
+
+    let json = args.predictargs.format == PredictFormat::Json;
+    let header = if json { "[\n".to_string() } else { format!("{}\n", prediction::CSV_HEADER) };
+    if !output(&mut sink, header.as_bytes())? {
+        return Ok(Some(Stop::BrokenPipe));
+    }
+
+    let steps = (duration / step).floor() as u64;
+    for i in 0..steps + 1 {
+        if let Some(signal) = signal::received() {
+            return Ok(Some(Stop::Signal(signal)));
         }
+
+        let time = start_time + time::Duration::nanoseconds((i as f64 * step * 1e9).round() as i64);
+        let prediction = predictor.at(time);
+        if !prediction.doppler_hz.is_finite() {
+            return Err(Error::Prediction(format!("can't predict doppler at {}", format_time(&time))));
+        }
+
+        let row = if json {
+            format!("  {}{}\n", prediction.to_json(), if i < steps { "," } else { "" })
+        }
+        else {
+            format!("{}\n", prediction.to_csv())
+        };
+        if !output(&mut sink, row.as_bytes())? {
+            return Ok(Some(Stop::BrokenPipe));
+        }
+    }
+
+    if json && !output(&mut sink, b"]\n")? {
+        return Ok(Some(Stop::BrokenPipe));
+    }
+    Ok(None)
+}
+
+/// Reads name and two lines of the given satellite from TLE file.
+fn read_tle_lines(tlefile: &str, tlename: &str) -> Result<Vec<String>, Error> {
+    let mut content = String::new();
+    let mut file = File::open(tlefile).map_err(|e| Error::Tle(format!("can't open {}: {}", tlefile, e)))?;
+    file.read_to_string(&mut content).map_err(|e| Error::Tle(format!("can't read {}: {}", tlefile, e)))?;
+
+    let lines: Vec<&str> = content.lines().map(|l| l.trim()).collect();
+    match lines.iter().position(|l| *l == tlename) {
+        Some(n) if n + 2 < lines.len() => Ok(lines[n..n + 3].iter().map(|l| l.to_string()).collect()),
+        _ => Err(Error::Tle(format!("{} not found in {}", tlename, tlefile))),
+    }
+}
+
+fn log_status(prediction: &Prediction, frequency: u32) {
+    info!("time                : {}", format_time(&prediction.time));
+    info!("az                  : {:.2}°", prediction.az_deg);
+    info!("el                  : {:.2}°", prediction.el_deg);
+    info!("range               : {:.0} km", prediction.range_km);
+    info!("range rate          : {:.3} km/sec", prediction.range_rate_km_sec);
+    info!("range acceleration  : {:.5} km/sec²", prediction.range_accel_km_sec2);
+    info!("doppler@{:.3} MHz : {:.2} Hz", frequency as f32 / 1_000_000_f32, prediction.doppler_hz);
+    info!("doppler rate        : {:.2} Hz/sec", prediction.doppler_rate_hz_sec);
+}
+
+fn report_truncated(bytes: usize) {
+    if bytes > 0 {
+        warn!("input ended in the middle of a sample, last {} bytes were ignored", bytes);
+    }
+}
+
+fn report_clipped(clipped: u64) {
+    if clipped > 0 {
+        warn!("{} samples were clipped in output, consider lowering --gain", clipped);
     }
 }
 
@@ -213,7 +713,7 @@ fn setup_logger() {
     let logger_config = fern::DispatchConfig {
         format: Box::new(|msg: &str, level: &log::LogLevel, _location: &log::LogLocation| {
             let t = time::now();
-            let ms = t.tm_nsec/1000_000;
+            let ms = t.tm_nsec/1_000_000;
             let path = _location.__module_path;
             let line = _location.__line;
 
@@ -0,0 +1,99 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+// Read-only memory mapping of a file region, recordings are read through it without copying.
+
+use libc;
+use error::{Error, Result};
+
+use std::convert::TryFrom;
+use std::fs::File;
+use std::os::unix::io::AsRawFd;
+use std::ptr;
+use std::slice;
+
+pub struct Mmap {
+    ptr: *mut libc::c_void,
+    // mapping starts from page boundary, requested region begins `start` bytes later
+    start: usize,
+    size: usize,
+}
+
+// mapping is read-only, so it can be shared between threads
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+impl Mmap {
+    /// Maps `length` bytes of the file starting from `offset`.
+    /// Returns None if the region does not fit into address space, eg. 4 GiB recording on a 32-bit target.
+    pub fn open(path: &str, offset: u64, length: u64) -> Result<Option<Mmap>> {
+        if length == 0 {
+            return Ok(Some(Mmap{ptr: ptr::null_mut(), start: 0, size: 0}));
+        }
+
+        let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as u64;
+        let aligned = offset - offset % page;
+        let start = (offset - aligned) as usize;
+        let size = match usize::try_from(length).ok().and_then(|length| length.checked_add(start)) {
+            Some(size) => size,
+            None => return Ok(None),
+        };
+        let aligned = match libc::off_t::try_from(aligned) {
+            Ok(aligned) => aligned,
+            Err(_) => return Ok(None),
+        };
+
+        let file = File::open(path).map_err(|e| Error::io(format!("can't open {}: {}", path, e), &e))?;
+
+        let ptr = unsafe {
+            libc::mmap(ptr::null_mut(), size, libc::PROT_READ, libc::MAP_PRIVATE, file.as_raw_fd(), aligned)
+        };
+
+        if ptr == libc::MAP_FAILED {
+            let e = ::std::io::Error::last_os_error();
+            return Err(Error::io(format!("can't map {}: {}", path, e), &e));
+        }
+
+        // data is read sequentially by every thread
+        unsafe { libc::madvise(ptr, size, libc::MADV_SEQUENTIAL) };
+
+        Ok(Some(Mmap{ptr, start, size}))
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        if self.ptr.is_null() {
+            return &[];
+        }
+
+        unsafe { slice::from_raw_parts((self.ptr as *const u8).add(self.start), self.size - self.start) }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        if !self.ptr.is_null() {
+            unsafe { libc::munmap(self.ptr, self.size) };
+        }
+    }
+}
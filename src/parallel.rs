@@ -0,0 +1,152 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+// Parallel doppler correction of recordings.
+//
+// Doppler profile of the whole recording is predicted ahead and NCO state at the beginning of every
+// block is computed from it, so blocks can be corrected on any thread in any order. Output is
+// bit-identical to serial processing because each block starts with exactly the same NCO and
+// quantizer state that it would have had in the serial path.
+
+use dsp;
+use dsp::{Nco, Quantizer};
+use usage::{DataType, Endianness};
+
+use std::cmp;
+use std::convert::TryFrom;
+use std::thread;
+
+/// Block of samples with oscillator state at its beginning.
+pub struct Block {
+    // index of the first sample in the recording
+    pub sample: u64,
+    pub samples: usize,
+    pub nco: Nco,
+}
+
+/// Sample formats and output stage settings.
+pub struct Format {
+    pub intype: DataType,
+    pub inendianness: Endianness,
+    pub outtype: DataType,
+    pub outendianness: Endianness,
+    pub gain: f32,
+    pub dither: bool,
+}
+
+fn process_serial(input: &[u8], blocks: &[Block], format: &Format) -> (Vec<u8>, u64) {
+    let insize = format.intype.sample_size();
+    let mut output = Vec::with_capacity(blocks.iter().map(|b| b.samples).sum::<usize>() * format.outtype.sample_size());
+    let mut samples = Vec::new();
+    let mut outbuf = Vec::new();
+    let mut clipped = 0;
+
+    for block in blocks {
+        // last block may extend past the end of the recording
+        let available = input.len() - input.len() % insize;
+        let start = usize::try_from(block.sample).ok().and_then(|sample| sample.checked_mul(insize)).unwrap_or(available);
+        let start = cmp::min(start, available);
+        let end = cmp::min(start.saturating_add(block.samples * insize), available);
+        dsp::convert_to_complex_into(&input[start..end], format.intype, format.inendianness, &mut samples);
+
+        let mut nco = block.nco.clone();
+        nco.mix_in_place(&mut samples);
+
+        if format.gain != 0.0 {
+            dsp::apply_gain(&mut samples, format.gain);
+        }
+
+        let mut quantizer = Quantizer::at(format.dither, block.sample);
+        dsp::convert_from_complex_into(&samples, format.outtype, format.outendianness, &mut quantizer, &mut outbuf);
+        output.extend_from_slice(&outbuf);
+        clipped += quantizer.clipped();
+    }
+
+    (output, clipped)
+}
+
+/// Corrects blocks on `threads` threads. `input` holds the whole recording,
+/// output of the blocks is returned in order together with the number of clipped samples.
+pub fn process(input: &[u8], blocks: &[Block], format: &Format, threads: usize) -> (Vec<u8>, u64) {
+    if threads <= 1 || blocks.len() <= 1 {
+        return process_serial(input, blocks, format);
+    }
+
+    let per_thread = blocks.len().div_ceil(threads);
+    let results: Vec<(Vec<u8>, u64)> = thread::scope(|scope| {
+        let workers: Vec<_> = blocks.chunks(per_thread)
+                                    .map(|chunk| scope.spawn(move || process_serial(input, chunk, format)))
+                                    .collect();
+
+        workers.into_iter().map(|w| w.join().unwrap()).collect()
+    });
+
+    let mut output = Vec::with_capacity(results.iter().map(|r| r.0.len()).sum());
+    let mut clipped = 0;
+    for (bytes, count) in results {
+        output.extend(bytes);
+        clipped += count;
+    }
+
+    (output, clipped)
+}
+
+#[test]
+fn test_parallel_equals_serial() {
+    use corrector::{test_corrector, Sweep};
+
+    let format = Format {
+        intype: DataType::I16,
+        inendianness: Endianness::Little,
+        outtype: DataType::I16,
+        outendianness: Endianness::Little,
+        gain: 6.0,
+        dither: true,
+    };
+
+    let input: Vec<u8> = (0..4 * 20000).map(|n: u32| (n.wrapping_mul(2654435761) >> 13) as u8).collect();
+
+    for sweep in &[Sweep::Step, Sweep::Chirp, Sweep::Rate] {
+        // serial path: one corrector and quantizer through all samples
+        let mut samples = dsp::convert_to_complex(&input, format.intype, format.inendianness);
+        test_corrector(*sweep).process_in_place(&mut samples);
+        dsp::apply_gain(&mut samples, format.gain);
+        let mut quantizer = Quantizer::new(format.dither);
+        let serial = dsp::convert_from_complex(&samples, format.outtype, format.outendianness, &mut quantizer);
+
+        let blocks = test_corrector(*sweep).schedule(input.len() / 4);
+        assert!(blocks.len() > 4);
+
+        for threads in 1..5 {
+            let (parallel, clipped) = process(&input, &blocks, &format, threads);
+            assert!(parallel == serial, "{:?} parallel output differs from serial", sweep);
+            assert_eq!(clipped, quantizer.clipped());
+        }
+
+        // blocks can be processed in any grouping
+        let (first, _) = process(&input, &blocks[..3], &format, 2);
+        let (rest, _) = process(&input, &blocks[3..], &format, 4);
+        assert!([first, rest].concat() == serial);
+    }
+}
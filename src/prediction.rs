@@ -0,0 +1,190 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+// Satellite position, doppler and doppler rate predicted from TLE.
+//
+// Range acceleration is not given by SGP4, it is found from range rate by central finite difference.
+
+use serde_json::{Map, Value};
+use time;
+use gpredict::{Predict, Tle, Location};
+use clock::format_time;
+
+pub const SPEED_OF_LIGHT_M_S: f64 = 299792458.;
+
+// half of the interval over which range rate is differentiated
+const DIFFERENCE_STEP_MS: i64 = 500;
+// steps for finding the next pass and its largest doppler rate
+const SEARCH_STEP_S: i64 = 30;
+const PASS_STEP_S: i64 = 5;
+
+/// Returns doppler shift in Hz of a signal on `frequency` if range changes at `range_rate_km_sec`.
+/// Doppler rate in Hz/s is returned the same way from range acceleration.
+pub fn doppler_hz(range_rate_km_sec: f64, frequency: f64) -> f64 {
+    -(range_rate_km_sec * 1000_f64 / SPEED_OF_LIGHT_M_S) * frequency
+}
+
+/// Satellite position and doppler at one moment.
+#[derive(Clone)]
+pub struct Prediction {
+    pub time: time::Tm,
+    pub az_deg: f64,
+    pub el_deg: f64,
+    pub range_km: f64,
+    pub range_rate_km_sec: f64,
+    pub range_accel_km_sec2: f64,
+    pub doppler_hz: f64,
+    pub doppler_rate_hz_sec: f64,
+}
+
+/// Column names of `to_csv` rows.
+pub const CSV_HEADER: &str = "time,az_deg,el_deg,range_km,range_rate_km_sec,doppler_hz,doppler_rate_hz_sec";
+
+impl Prediction {
+    pub fn to_csv(&self) -> String {
+        format!("{},{:.3},{:.3},{:.3},{:.6},{:.3},{:.4}",
+                format_time(&self.time), self.az_deg, self.el_deg, self.range_km,
+                self.range_rate_km_sec, self.doppler_hz, self.doppler_rate_hz_sec)
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut prediction = Map::new();
+        prediction.insert("time".to_string(), format_time(&self.time).into());
+        prediction.insert("az_deg".to_string(), self.az_deg.into());
+        prediction.insert("el_deg".to_string(), self.el_deg.into());
+        prediction.insert("range_km".to_string(), self.range_km.into());
+        prediction.insert("range_rate_km_sec".to_string(), self.range_rate_km_sec.into());
+        prediction.insert("doppler_hz".to_string(), self.doppler_hz.into());
+        prediction.insert("doppler_rate_hz_sec".to_string(), self.doppler_rate_hz_sec.into());
+        Value::Object(prediction)
+    }
+}
+
+/// Predicts satellite signal doppler for an observer.
+pub struct Predictor {
+    predict: Predict,
+    frequency: f64,
+}
+
+impl Predictor {
+    pub fn new(tle: &Tle, location: &Location, frequency: f64) -> Predictor {
+        Predictor {
+            predict: Predict::new(tle, location),
+            frequency,
+        }
+    }
+
+    /// Doppler in Hz at `time`, faster than `at` because rate is not calculated.
+    pub fn doppler_hz(&mut self, time: time::Tm) -> f64 {
+        self.predict.update(Some(time));
+        doppler_hz(self.predict.sat.range_rate_km_sec, self.frequency)
+    }
+
+    pub fn at(&mut self, time: time::Tm) -> Prediction {
+        let step = time::Duration::milliseconds(DIFFERENCE_STEP_MS);
+
+        self.predict.update(Some(time - step));
+        let before = self.predict.sat.range_rate_km_sec;
+        self.predict.update(Some(time + step));
+        let after = self.predict.sat.range_rate_km_sec;
+        let range_accel = (after - before) / (2 * DIFFERENCE_STEP_MS) as f64 * 1000.0;
+
+        self.predict.update(Some(time));
+        Prediction {
+            time,
+            az_deg: self.predict.sat.az_deg,
+            el_deg: self.predict.sat.el_deg,
+            range_km: self.predict.sat.range_km,
+            range_rate_km_sec: self.predict.sat.range_rate_km_sec,
+            range_accel_km_sec2: range_accel,
+            doppler_hz: doppler_hz(self.predict.sat.range_rate_km_sec, self.frequency),
+            doppler_rate_hz_sec: doppler_hz(range_accel, self.frequency),
+        }
+    }
+
+    /// Finds the largest doppler rate of the pass that is in progress at `from` or of the next pass within a day.
+    /// Returns prediction at that moment, None if satellite does not rise above horizon.
+    pub fn max_doppler_rate(&mut self, from: time::Tm) -> Option<Prediction> {
+        let end = from + time::Duration::days(1);
+        let mut time = from;
+
+        loop {
+            self.predict.update(Some(time));
+            if self.predict.sat.el_deg >= 0.0 {
+                break;
+            }
+
+            time = time + time::Duration::seconds(SEARCH_STEP_S);
+            if time > end {
+                return None;
+            }
+        }
+
+        // doppler rate is largest near TCA, it is found from predictions until LOS
+        let mut max: Option<Prediction> = None;
+        while time <= end {
+            let prediction = self.at(time);
+            if prediction.el_deg < 0.0 {
+                break;
+            }
+
+            if max.as_ref().is_none_or(|m| prediction.doppler_rate_hz_sec.abs() > m.doppler_rate_hz_sec.abs()) {
+                max = Some(prediction);
+            }
+
+            time = time + time::Duration::seconds(PASS_STEP_S);
+        }
+
+        max
+    }
+}
+
+#[test]
+fn test_prediction_output() {
+    let prediction = Prediction {
+        time: ::usage::parse_time("2015-01-22T09:07:16.250").unwrap(),
+        az_deg: 123.4,
+        el_deg: 45.6,
+        range_km: 789.0,
+        range_rate_km_sec: -6.5,
+        range_accel_km_sec2: 0.01,
+        doppler_hz: 9485.8,
+        doppler_rate_hz_sec: -145.9,
+    };
+
+    assert_eq!(prediction.to_csv(), "2015-01-22T09:07:16.250Z,123.400,45.600,789.000,-6.500000,9485.800,-145.9000");
+    assert_eq!(CSV_HEADER.split(',').count(), prediction.to_csv().split(',').count());
+
+    let json = prediction.to_json();
+    assert_eq!(json.get("time").and_then(|t| t.as_str()), Some("2015-01-22T09:07:16.250Z"));
+    assert_eq!(json.get("doppler_rate_hz_sec").and_then(|d| d.as_f64()), Some(-145.9));
+}
+
+#[test]
+fn test_doppler_hz() {
+    // approaching satellite increases frequency
+    let doppler = doppler_hz(-7.0, 437505000.0);
+    assert!((doppler - 10215.52).abs() < 0.01);
+    assert_eq!(doppler_hz(7.0, 437505000.0), -doppler);
+}
@@ -0,0 +1,232 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+// Finds recording parameters from SigMF metadata, WAV header or file name conventions,
+// so that they do not have to be typed in by hand.
+
+use time;
+use sigmf;
+use wav;
+use usage::{DataType, Endianness};
+use error::Result;
+
+use std::path::Path;
+
+/// Recording parameters that could be found, unknown ones are None.
+pub struct Recording {
+    // where parameters were taken from, used in error messages
+    pub source: String,
+    pub datafile: String,
+
+    // position and length of IQ data in the data file
+    pub data_offset: u64,
+    pub data_size: Option<u64>,
+
+    pub datatype: Option<(DataType, Endianness)>,
+    pub samplerate: Option<u32>,
+    pub frequency: Option<f64>,
+    pub datetime: Option<time::Tm>,
+}
+
+pub fn from_sigmf(metafile: &str) -> Result<Recording> {
+    let meta = sigmf::read_meta(metafile)?;
+
+    Ok(Recording {
+        source: format!("SigMF metadata {}", metafile),
+        datafile: meta.datafile,
+        data_offset: 0,
+        data_size: None,
+        datatype: Some((meta.datatype, meta.endianness)),
+        samplerate: Some(meta.samplerate),
+        frequency: meta.frequency,
+        datetime: meta.datetime,
+    })
+}
+
+pub fn from_wav(wavfile: &str) -> Result<Recording> {
+    let header = wav::read_header(wavfile)?;
+
+    // SDR# puts time and frequency also to the file name, it helps if auxi chunk is missing
+    let name = from_filename(wavfile);
+
+    Ok(Recording {
+        source: format!("WAV file {}", wavfile),
+        datafile: wavfile.to_string(),
+        data_offset: header.data_offset,
+        data_size: header.data_size,
+        datatype: Some((header.datatype, Endianness::Little)),
+        samplerate: Some(header.samplerate),
+        frequency: header.frequency.or(name.frequency),
+        datetime: header.datetime.or(name.datetime),
+    })
+}
+
+fn parse_extension(extension: &str) -> Option<(DataType, Endianness)> {
+    match extension {
+        "cu8" => Some((DataType::U8, Endianness::Little)),
+        "cs8" => Some((DataType::I8, Endianness::Little)),
+        "cs16" => Some((DataType::I16, Endianness::Little)),
+        "cs32" => Some((DataType::I32, Endianness::Little)),
+        "cf32" | "cfile" => Some((DataType::F32, Endianness::Little)),
+        "cf64" => Some((DataType::F64, Endianness::Little)),
+        _ => None,
+    }
+}
+
+fn parse_date(date: &str, time: &str) -> Option<time::Tm> {
+    if date.len() != 8 || time.len() != 6 {
+        return None;
+    }
+
+    time::strptime(&format!("{}T{}", date, time), "%Y%m%dT%H%M%S").ok()
+}
+
+/// Finds parameters from raw IQ file name.
+/// GQRX: gqrx_20150122_090716_437500000_1024000_fc.raw
+/// SDR#: SDRSharp_20150122_090716Z_437500000Hz_IQ.wav
+/// Data type is taken from extension: .cu8, .cs8, .cs16, .cs32, .cf32, .cfile or .cf64
+pub fn from_filename(path: &str) -> Recording {
+    let file = Path::new(path);
+    let stem = file.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    let extension = file.extension().and_then(|s| s.to_str()).unwrap_or("");
+
+    let mut recording = Recording {
+        source: format!("file name {}", path),
+        datafile: path.to_string(),
+        data_offset: 0,
+        data_size: None,
+        datatype: parse_extension(&extension.to_lowercase()),
+        samplerate: None,
+        frequency: None,
+        datetime: None,
+    };
+
+    let parts: Vec<&str> = stem.split('_').collect();
+
+    if parts.len() >= 5 && parts[0] == "gqrx" {
+        // GQRX writes native complex float
+        recording.datetime = parse_date(parts[1], parts[2]);
+        recording.frequency = parts[3].parse::<f64>().ok();
+        recording.samplerate = parts[4].parse::<u32>().ok().filter(|&s| s > 0);
+        if recording.datatype.is_none() && extension == "raw" {
+            recording.datatype = Some((DataType::F32, Endianness::Little));
+        }
+    }
+    else {
+        // SDR# marks UTC time with Z suffix and center frequency follows it,
+        // other date and time pairs could be local time and are not trusted
+        for n in 0..parts.len().saturating_sub(1) {
+            let datetime = parts[n + 1].strip_suffix('Z').and_then(|time| parse_date(parts[n], time));
+            if datetime.is_some() {
+                recording.datetime = datetime;
+                recording.frequency = parts.get(n + 2).and_then(|p| p.strip_suffix("Hz")).and_then(|f| f.parse::<f64>().ok());
+                break;
+            }
+        }
+    }
+
+    recording
+}
+
+const CORRECTED_SUFFIX: &str = "_doppler";
+
+/// Returns path for doppler corrected recording: eg. dir/pass.cs16 -> dir/pass_doppler.cs16
+/// SigMF recordings are named by their .sigmf-meta file.
+pub fn corrected_path(path: &str) -> String {
+    for extension in &[sigmf::META_EXTENSION, sigmf::DATA_EXTENSION] {
+        if let Some(basename) = path.strip_suffix(extension) {
+            return format!("{}{}{}", basename, CORRECTED_SUFFIX, sigmf::META_EXTENSION);
+        }
+    }
+
+    let file = Path::new(path);
+    let stem = file.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    let name = match file.extension().and_then(|s| s.to_str()) {
+        Some(extension) => format!("{}{}.{}", stem, CORRECTED_SUFFIX, extension),
+        None => format!("{}{}", stem, CORRECTED_SUFFIX),
+    };
+
+    file.with_file_name(name).to_string_lossy().into_owned()
+}
+
+/// Returns true if file is output of corrected_path.
+pub fn is_corrected(path: &str) -> bool {
+    let path = path.trim_end_matches(sigmf::META_EXTENSION).trim_end_matches(sigmf::DATA_EXTENSION);
+    Path::new(path).file_stem().and_then(|s| s.to_str()).map(|s| s.ends_with(CORRECTED_SUFFIX)).unwrap_or(false)
+}
+
+/// Detects recording parameters based on file extension and headers.
+pub fn detect(path: &str) -> Result<Recording> {
+    if path.ends_with(sigmf::META_EXTENSION) {
+        from_sigmf(path)
+    }
+    else if let Some(basename) = path.strip_suffix(sigmf::DATA_EXTENSION) {
+        from_sigmf(&format!("{}{}", basename, sigmf::META_EXTENSION))
+    }
+    else if path.to_lowercase().ends_with(".wav") {
+        from_wav(path)
+    }
+    else {
+        Ok(from_filename(path))
+    }
+}
+
+#[test]
+fn test_from_filename() {
+    let r = from_filename("passes/gqrx_20150122_090716_437500000_1024000_fc.raw");
+    assert!(r.datatype == Some((DataType::F32, Endianness::Little)));
+    assert_eq!(r.samplerate, Some(1024000));
+    assert_eq!(r.frequency, Some(437500000.0));
+    assert_eq!(r.datetime.unwrap().to_timespec(), ::usage::parse_time("2015-01-22T09:07:16").unwrap().to_timespec());
+
+    let r = from_filename("SDRSharp_20150122_090716Z_437500000Hz_IQ.wav");
+    assert!(r.datatype.is_none());
+    assert_eq!(r.frequency, Some(437500000.0));
+    assert!(r.datetime.is_some());
+
+    // time without Z suffix is not known to be UTC
+    let r = from_filename("pass_20150122_090716_437500000Hz.cs16");
+    assert!(r.datetime.is_none());
+    assert!(r.frequency.is_none());
+
+    let r = from_filename("pass.cu8");
+    assert!(r.datatype == Some((DataType::U8, Endianness::Little)));
+    assert!(r.samplerate.is_none());
+    assert!(r.frequency.is_none());
+    assert!(r.datetime.is_none());
+
+    assert!(from_filename("pass.iq").datatype.is_none());
+}
+
+#[test]
+fn test_corrected_path() {
+    assert_eq!(corrected_path("passes/pass.cs16"), "passes/pass_doppler.cs16");
+    assert_eq!(corrected_path("pass.sigmf-data"), "pass_doppler.sigmf-meta");
+    assert_eq!(corrected_path("pass.sigmf-meta"), "pass_doppler.sigmf-meta");
+    assert_eq!(corrected_path("pass"), "pass_doppler");
+
+    assert!(is_corrected("passes/pass_doppler.cs16"));
+    assert!(is_corrected("pass_doppler.sigmf-meta"));
+    assert!(!is_corrected("pass.sigmf-meta"));
+}
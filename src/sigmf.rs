@@ -0,0 +1,276 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+// SigMF recording support: https://github.com/gnuradio/SigMF
+// A recording consists of a .sigmf-meta JSON file and a .sigmf-data file with raw IQ samples.
+
+use serde_json::{self, Map, Value};
+use time;
+use clock::format_time;
+use usage::{DataType, Endianness, Location, parse_time};
+use error::{Error, Result};
+
+use std::fs::File;
+use std::io::prelude::*;
+
+pub const META_EXTENSION: &str = ".sigmf-meta";
+pub const DATA_EXTENSION: &str = ".sigmf-data";
+
+/// Recording parameters that are read from a .sigmf-meta file.
+pub struct Meta {
+    pub datatype: DataType,
+    pub endianness: Endianness,
+    pub samplerate: u32,
+    pub frequency: Option<f64>,
+    pub datetime: Option<time::Tm>,
+    pub datafile: String,
+}
+
+/// Returns the name of .sigmf-data file that belongs to given .sigmf-meta file.
+pub fn data_path(metafile: &str) -> String {
+    let basename = metafile.strip_suffix(META_EXTENSION).unwrap_or(metafile);
+
+    format!("{}{}", basename, DATA_EXTENSION)
+}
+
+/// Parses SigMF complex data type: eg. ci16_le, cf32_be or cu8.
+pub fn parse_datatype(datatype: &str) -> Result<(DataType, Endianness)> {
+    let mut split = datatype.splitn(2, "_");
+    let name = split.next().unwrap();
+
+    let endianness = match split.next() {
+        Some("le") | None => Endianness::Little,
+        Some("be") => Endianness::Big,
+        Some(_) => return Err(Error::Format(format!("{} has unknown byte order", datatype))),
+    };
+
+    match name {
+        "cf32" => Ok((DataType::F32, endianness)),
+        "ci16" => Ok((DataType::I16, endianness)),
+        "cu8" => Ok((DataType::U8, endianness)),
+        "ci8" => Ok((DataType::I8, endianness)),
+        "ci32" => Ok((DataType::I32, endianness)),
+        "cf64" => Ok((DataType::F64, endianness)),
+        _ => Err(Error::Format(format!("SigMF data type {} is not supported", datatype))),
+    }
+}
+
+/// Formats data type as SigMF complex data type: eg. ci16_le, cf32_be or cu8.
+pub fn format_datatype(datatype: DataType, endianness: Endianness) -> String {
+    let name = match datatype {
+        DataType::F32 => "cf32",
+        DataType::I16 => "ci16",
+        DataType::U8 => return "cu8".to_string(),
+        DataType::I8 => return "ci8".to_string(),
+        DataType::I32 => "ci32",
+        DataType::F64 => "cf64",
+    };
+
+    match endianness {
+        Endianness::Little => format!("{}_le", name),
+        Endianness::Big => format!("{}_be", name),
+    }
+}
+
+fn parse_datetime(datetime: &str) -> Result<time::Tm> {
+    // SigMF uses ISO-8601 in UTC with Z suffix: eg. 2015-01-22T09:07:16.250Z
+    parse_time(datetime.trim_end_matches('Z')).map_err(|e| Error::Format(format!("invalid SigMF datetime {}: {}", datetime, e)))
+}
+
+pub fn parse_meta(json: &str, datafile: &str) -> Result<Meta> {
+    let meta: Value = serde_json::from_str(json).map_err(|e| Error::Format(format!("invalid SigMF metadata: {}", e)))?;
+
+    let datatype = meta.pointer("/global/core:datatype").and_then(|d| d.as_str());
+    let datatype = datatype.ok_or(Error::Format("SigMF metadata does not have core:datatype".to_string()))?;
+    let (datatype, endianness) = parse_datatype(datatype)?;
+
+    let samplerate = meta.pointer("/global/core:sample_rate").and_then(|s| s.as_f64());
+    let samplerate = samplerate.ok_or(Error::Format("SigMF metadata does not have core:sample_rate".to_string()))?;
+    if samplerate.is_nan() || samplerate.round() < 1.0 {
+        return Err(Error::Format(format!("SigMF core:sample_rate {} is not valid", samplerate)));
+    }
+
+    // only the first capture segment is used, it describes the beginning of the recording
+    let capture = meta.get("captures").and_then(|c| c.as_array()).and_then(|c| c.first());
+    let frequency = capture.and_then(|c| c.get("core:frequency")).and_then(|f| f.as_f64());
+
+    let datetime = match capture.and_then(|c| c.get("core:datetime")).and_then(|d| d.as_str()) {
+        Some(d) => Some(parse_datetime(d)?),
+        None => None,
+    };
+
+    Ok(Meta {
+        datatype,
+        endianness,
+        samplerate: samplerate.round() as u32,
+        frequency,
+        datetime,
+        datafile: datafile.to_string(),
+    })
+}
+
+pub fn read_meta(metafile: &str) -> Result<Meta> {
+    let mut json = String::new();
+    let mut file = File::open(metafile).map_err(|e| Error::io(format!("can't open {}: {}", metafile, e), &e))?;
+    file.read_to_string(&mut json).map_err(|e| Error::io(format!("can't read {}: {}", metafile, e), &e))?;
+
+    parse_meta(&json, &data_path(metafile))
+}
+
+pub struct Annotation {
+    pub sample_start: u64,
+    pub label: String,
+    pub comment: String,
+}
+
+/// Description of doppler corrected output that is written to a .sigmf-meta file.
+pub struct OutputMeta {
+    pub datatype: DataType,
+    pub endianness: Endianness,
+    pub samplerate: u32,
+    pub frequency: f64,
+    pub datetime: time::Tm,
+    pub tle: Vec<String>,
+    pub location: Location,
+    pub offset: i32,
+    pub annotations: Vec<Annotation>,
+}
+
+impl OutputMeta {
+    pub fn to_json(&self) -> Value {
+        let mut extension = Map::new();
+        extension.insert("name".to_string(), "doppler".into());
+        extension.insert("version".to_string(), env!("CARGO_PKG_VERSION").into());
+        extension.insert("optional".to_string(), true.into());
+
+        let mut location = Map::new();
+        location.insert("lat".to_string(), self.location.lat.into());
+        location.insert("lon".to_string(), self.location.lon.into());
+        location.insert("alt".to_string(), self.location.alt.into());
+
+        let mut global = Map::new();
+        global.insert("core:datatype".to_string(), format_datatype(self.datatype, self.endianness).into());
+        global.insert("core:sample_rate".to_string(), self.samplerate.into());
+        global.insert("core:version".to_string(), "1.0.0".into());
+        global.insert("core:recorder".to_string(), format!("doppler {}", env!("CARGO_PKG_VERSION")).into());
+        global.insert("core:description".to_string(), "Doppler corrected IQ data".into());
+        global.insert("core:extensions".to_string(), Value::Array(vec![Value::Object(extension)]));
+        global.insert("doppler:tle".to_string(), self.tle.clone().into());
+        global.insert("doppler:location".to_string(), Value::Object(location));
+        global.insert("doppler:offset".to_string(), self.offset.into());
+
+        let mut capture = Map::new();
+        capture.insert("core:sample_start".to_string(), 0.into());
+        capture.insert("core:frequency".to_string(), self.frequency.into());
+        capture.insert("core:datetime".to_string(), format_time(&self.datetime).into());
+
+        let mut annotations = Vec::new();
+        for a in &self.annotations {
+            let mut annotation = Map::new();
+            annotation.insert("core:sample_start".to_string(), a.sample_start.into());
+            annotation.insert("core:sample_count".to_string(), 1.into());
+            annotation.insert("core:label".to_string(), a.label.clone().into());
+            annotation.insert("core:comment".to_string(), a.comment.clone().into());
+            annotations.push(Value::Object(annotation));
+        }
+
+        let mut meta = Map::new();
+        meta.insert("global".to_string(), Value::Object(global));
+        meta.insert("captures".to_string(), Value::Array(vec![Value::Object(capture)]));
+        meta.insert("annotations".to_string(), Value::Array(annotations));
+
+        Value::Object(meta)
+    }
+
+    pub fn write(&self, metafile: &str) -> Result<()> {
+        let mut file = File::create(metafile).map_err(|e| Error::io(format!("can't create {}: {}", metafile, e), &e))?;
+        writeln!(file, "{:#}", self.to_json()).map_err(|e| Error::io(format!("can't write {}: {}", metafile, e), &e))
+    }
+}
+
+#[test]
+fn test_parse_meta() {
+    let json = r#"{
+        "global": {
+            "core:datatype": "ci16_le",
+            "core:sample_rate": 1024000,
+            "core:version": "0.0.1"
+        },
+        "captures": [
+            {
+                "core:sample_start": 0,
+                "core:frequency": 437500000,
+                "core:datetime": "2015-01-22T09:07:16.250Z"
+            }
+        ],
+        "annotations": []
+    }"#;
+
+    let meta = parse_meta(json, "pass.sigmf-data").unwrap();
+    assert!(meta.datatype == DataType::I16);
+    assert!(meta.endianness == Endianness::Little);
+    assert_eq!(meta.samplerate, 1024000);
+    assert_eq!(meta.frequency, Some(437500000.0));
+    assert_eq!(meta.datetime.unwrap(), parse_time("2015-01-22T09:07:16.250").unwrap());
+
+    assert_eq!(data_path("dir/pass.sigmf-meta"), "dir/pass.sigmf-data");
+
+    let json = json.replace("1024000", "0");
+    assert!(parse_meta(&json, "pass.sigmf-data").is_err());
+
+    // deeply nested metadata is rejected instead of overflowing the stack
+    let nested = format!("{}{}", "[".repeat(100000), "]".repeat(100000));
+    assert!(parse_meta(&nested, "pass.sigmf-data").is_err());
+    assert!(parse_datatype("rf32_le").is_err());
+}
+
+#[test]
+fn test_output_meta() {
+    let datetime = parse_time("2015-01-22T09:07:16.250").unwrap();
+
+    let meta = OutputMeta {
+        datatype: DataType::F32,
+        endianness: Endianness::Big,
+        samplerate: 256000,
+        frequency: 437505000.0,
+        datetime,
+        tle: vec!["ESTCUBE 1".to_string()],
+        location: Location{lat: 58.26541, lon: 26.46667, alt: 76.0},
+        offset: -2500,
+        annotations: vec![Annotation{sample_start: 1024, label: "AOS".to_string(), comment: "el 0.00".to_string()}],
+    };
+
+    // written metadata must be readable by the reader
+    let json = meta.to_json().to_string();
+    let read = parse_meta(&json, "pass.sigmf-data").unwrap();
+    assert!(read.datatype == DataType::F32);
+    assert!(read.endianness == Endianness::Big);
+    assert_eq!(read.samplerate, 256000);
+    assert_eq!(read.frequency, Some(437505000.0));
+    assert_eq!(read.datetime.unwrap(), datetime);
+
+    let json: Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(json.pointer("/global/doppler:offset").and_then(|o| o.as_i64()), Some(-2500));
+    assert_eq!(json.pointer("/annotations/0/core:label").and_then(|l| l.as_str()), Some("AOS"));
+}
@@ -0,0 +1,289 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+// Sources and sinks of IQ data, "-" stands for stdin or stdout.
+
+use error::{Error, Result};
+use signal;
+
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+
+pub const STDIO: &str = "-";
+
+/// Reads until buffer is full or input ends, returns number of bytes read.
+/// Unlike read_exact it tells how much was read if input ended in the middle of the buffer.
+pub fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut count = 0;
+
+    while count < buf.len() {
+        match reader.read(&mut buf[count..]) {
+            Ok(0) => break,
+            Ok(n) => count += n,
+            // read is interrupted by SIGINT or SIGTERM, input is treated as ended then
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted && signal::received().is_some() => break,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(count)
+}
+
+/// Reads input in blocks of whole samples.
+///
+/// Short reads, that pipes can return at any time, are collected until the block is full,
+/// so a block is shorter only at the end of input. Bytes of an incomplete last sample are dropped.
+pub struct BlockReader<R> {
+    reader: R,
+    sample_size: usize,
+    truncated: usize,
+}
+
+impl<R: Read> BlockReader<R> {
+    pub fn new(reader: R, sample_size: usize) -> BlockReader<R> {
+        BlockReader {
+            reader,
+            sample_size,
+            truncated: 0,
+        }
+    }
+
+    /// Fills buffer with whole samples and returns number of bytes in it,
+    /// less than buffer length means that input has ended.
+    pub fn read_block(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = read_full(&mut self.reader, buf)?;
+        let partial = count % self.sample_size;
+
+        if partial != 0 {
+            self.truncated = partial;
+        }
+
+        Ok(count - partial)
+    }
+
+    /// Number of bytes in the incomplete last sample that were dropped.
+    pub fn truncated(&self) -> usize {
+        self.truncated
+    }
+}
+
+/// Where IQ data is read from.
+pub enum Source {
+    Stdin(io::Stdin),
+    // pipe or device, eg. /dev/stdin or <(...), that can't be seeked or memory mapped
+    Stream {
+        path: String,
+        file: io::Take<File>,
+        length: Option<u64>,
+    },
+    File {
+        path: String,
+        file: io::Take<File>,
+        // IQ data position and length in the file
+        offset: u64,
+        length: u64,
+    },
+}
+
+impl Source {
+    /// Opens input, IQ data is read from offset up to length bytes or until the end of file.
+    pub fn open(path: &str, offset: u64, length: Option<u64>) -> Result<Source> {
+        if path == STDIO {
+            return Ok(Source::Stdin(io::stdin()));
+        }
+
+        let mut file = File::open(path).map_err(|e| Error::io(format!("can't open {}: {}", path, e), &e))?;
+        let metadata = file.metadata().map_err(|e| Error::io(format!("can't read {}: {}", path, e), &e))?;
+
+        // pipes can't be seeked, so it is done only if IQ data does not start from the beginning
+        if offset > 0 {
+            file.seek(SeekFrom::Start(offset)).map_err(|e| Error::io(format!("can't seek {}: {}", path, e), &e))?;
+        }
+
+        if !metadata.is_file() {
+            return Ok(Source::Stream {
+                path: path.to_string(),
+                file: file.take(length.unwrap_or(u64::MAX)),
+                length,
+            });
+        }
+
+        // stop at the end of IQ data, eg. WAV chunks after it are not samples,
+        // header of a truncated recording can claim more data than the file has
+        let available = metadata.len().saturating_sub(offset);
+        let length = match length {
+            Some(length) if length > available => {
+                warn!("{} is truncated, it has {} bytes of IQ data instead of {}", path, available, length);
+                available
+            },
+            Some(length) => length,
+            None => available,
+        };
+
+        Ok(Source::File {
+            path: path.to_string(),
+            file: file.take(length),
+            offset,
+            length,
+        })
+    }
+
+    pub fn path(&self) -> Option<&str> {
+        match *self {
+            Source::Stdin(_) => None,
+            Source::Stream{ref path, ..} => Some(path),
+            Source::File{ref path, ..} => Some(path),
+        }
+    }
+
+    /// Length of IQ data in bytes, it is not known for stdin and pipes.
+    pub fn length(&self) -> Option<u64> {
+        match *self {
+            Source::Stdin(_) => None,
+            Source::Stream{length, ..} => length,
+            Source::File{length, ..} => Some(length),
+        }
+    }
+}
+
+impl Read for Source {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Source::Stdin(ref mut stdin) => stdin.read(buf),
+            Source::Stream{ref mut file, ..} => file.read(buf),
+            Source::File{ref mut file, ..} => file.read(buf),
+        }
+    }
+}
+
+/// Where IQ data is written to.
+pub enum Sink {
+    Stdout(io::Stdout),
+    File {
+        path: String,
+        file: File,
+    },
+}
+
+impl Sink {
+    pub fn create(path: &str) -> Result<Sink> {
+        if path == STDIO {
+            return Ok(Sink::Stdout(io::stdout()));
+        }
+
+        let file = File::create(path).map_err(|e| Error::io(format!("can't create {}: {}", path, e), &e))?;
+        Ok(Sink::File {
+            path: path.to_string(),
+            file,
+        })
+    }
+
+    pub fn path(&self) -> Option<&str> {
+        match *self {
+            Sink::Stdout(_) => None,
+            Sink::File{ref path, ..} => Some(path),
+        }
+    }
+
+    /// True if output is a regular file that can be seeked, not stdout or a pipe.
+    pub fn is_regular_file(&self) -> bool {
+        match *self {
+            Sink::Stdout(_) => false,
+            Sink::File{ref file, ..} => file.metadata().map(|m| m.is_file()).unwrap_or(false),
+        }
+    }
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            Sink::Stdout(ref mut stdout) => stdout.write(buf),
+            Sink::File{ref mut file, ..} => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            Sink::Stdout(ref mut stdout) => stdout.flush(),
+            Sink::File{ref mut file, ..} => file.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+struct Trickle {
+    data: Vec<u8>,
+    position: usize,
+}
+
+// returns at most 3 bytes per read like a slow pipe
+#[cfg(test)]
+impl Read for Trickle {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = *[3, buf.len(), self.data.len() - self.position].iter().min().unwrap();
+        buf[..count].copy_from_slice(&self.data[self.position..self.position + count]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+#[test]
+fn test_block_reader() {
+    let data: Vec<u8> = (0..22).collect();
+    let mut reader = BlockReader::new(Trickle{data: data.clone(), position: 0}, 4);
+    let mut buf = [0; 8];
+
+    // short reads do not end the input and samples are not split between blocks
+    assert_eq!(reader.read_block(&mut buf).unwrap(), 8);
+    assert_eq!(&buf, &data[0..8]);
+    assert_eq!(reader.read_block(&mut buf).unwrap(), 8);
+    assert_eq!(reader.truncated(), 0);
+
+    // 6 bytes are left: one sample and 2 bytes of an incomplete one
+    assert_eq!(reader.read_block(&mut buf).unwrap(), 4);
+    assert_eq!(&buf[..4], &data[16..20]);
+    assert_eq!(reader.truncated(), 2);
+    assert_eq!(reader.read_block(&mut buf).unwrap(), 0);
+}
+
+#[test]
+fn test_truncated_source() {
+    let path = ::std::env::temp_dir().join(format!("doppler_test_truncated_{}.wav", ::std::process::id()));
+    ::std::fs::write(&path, vec![0; 100]).unwrap();
+    let path = path.to_str().unwrap().to_string();
+
+    // header claims 10 MB of data after 44 byte header, but the file ends before that
+    let source = Source::open(&path, 44, Some(10_000_000));
+    ::std::fs::remove_file(&path).unwrap();
+
+    let mut source = source.unwrap();
+    assert_eq!(source.length(), Some(56));
+
+    let mut data = vec![];
+    source.read_to_end(&mut data).unwrap();
+    assert_eq!(data.len(), 56);
+}
@@ -22,23 +22,51 @@
  * SOFTWARE.
  */
 
-use clap::{App, AppSettings, Arg, SubCommand};
+use clap::{App, AppSettings, Arg, ArgMatches, ErrorKind, SubCommand};
 use time;
-use self::DataType::{F32, I16};
-use self::Mode::{ConstMode, TrackMode};
+use recording;
+use recording::Recording;
+use stream::STDIO;
+use sigmf;
+use error::{Error, Result};
+use self::DataType::{F32, I16, U8, I8, I32, F64};
+use self::Mode::{ConstMode, TrackMode, BatchMode, PredictMode};
 
 use std::fmt;
-use std::process::exit;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
 
+#[derive(Clone)]
 pub enum Mode {
     ConstMode,
     TrackMode,
+    BatchMode,
+    PredictMode,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 pub enum DataType {
     F32,
     I16,
+    U8,
+    I8,
+    I32,
+    F64,
+}
+
+impl DataType {
+    /// Size of one IQ sample in bytes.
+    pub fn sample_size(&self) -> usize {
+        match *self {
+            DataType::F32 => 8,
+            DataType::I16 => 4,
+            DataType::U8 => 2,
+            DataType::I8 => 2,
+            DataType::I32 => 8,
+            DataType::F64 => 16,
+        }
+    }
 }
 
 impl fmt::Display for DataType {
@@ -46,6 +74,26 @@ impl fmt::Display for DataType {
         match *self {
             DataType::F32 => {write!(f, "f32")},
             DataType::I16 => {write!(f, "i16")},
+            DataType::U8 => {write!(f, "u8")},
+            DataType::I8 => {write!(f, "i8")},
+            DataType::I32 => {write!(f, "i32")},
+            DataType::F64 => {write!(f, "f64")},
+        }
+    }
+}
+
+/// Byte order of multi-byte sample types, 8-bit types do not depend on it.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+impl fmt::Display for Endianness {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Endianness::Little => {write!(f, "little endian")},
+            Endianness::Big => {write!(f, "big endian")},
         }
     }
 }
@@ -58,10 +106,12 @@ pub struct Location {
     pub alt: f64,
 }
 
+#[derive(Clone)]
 pub struct ConstModeArgs {
     pub shift: Option<i32>,
 }
 
+#[derive(Clone)]
 pub struct TrackModeArgs {
     pub tlefile: Option<String>,
     pub tlename: Option<String>,
@@ -69,20 +119,220 @@ pub struct TrackModeArgs {
     pub time: Option<time::Tm>,
     pub frequency: Option<u32>,
     pub offset: Option<i32>,
+    pub chirp: bool,
+    // correction follows predicted doppler rate between updates
+    pub dopplerrate: bool,
+    // doppler updates per second
+    pub updaterate: Option<f64>,
+
+    // center frequency of the input recording if it is known
+    pub capturefrequency: Option<f64>,
+    pub outputsigmf: Option<String>,
+    pub outputwav: Option<String>,
+}
+
+/// Output format of predict mode.
+#[derive(Clone, Copy, PartialEq)]
+pub enum PredictFormat {
+    Csv,
+    Json,
 }
 
+#[derive(Clone)]
+pub struct PredictModeArgs {
+    // length and resolution of the predicted doppler curve in seconds
+    pub duration: f64,
+    pub step: f64,
+    pub format: PredictFormat,
+}
+
+#[derive(Clone)]
+pub struct BatchModeArgs {
+    // recordings that are processed one by one with shared track mode arguments
+    pub files: Vec<String>,
+}
+
+#[derive(Clone)]
 pub struct CommandArgs {
     pub mode: Option<Mode>,
 
     pub samplerate: Option<u32>,
     pub inputtype: Option<DataType>,
+    pub inputendianness: Option<Endianness>,
     pub outputtype: Option<DataType>,
+    pub outputendianness: Option<Endianness>,
+    pub gain: Option<f32>,
+    pub dither: bool,
+    // number of threads for correcting recordings, all cores are used if not set
+    pub threads: Option<usize>,
+    // number of samples in an I/O block
+    pub blocksamples: Option<usize>,
+
+    // file paths, - is stdin or stdout
+    pub input: String,
+    pub output: String,
+    // IQ data location in input file, eg. WAV header is skipped
+    pub inputoffset: u64,
+    pub inputlength: Option<u64>,
 
     pub constargs: ConstModeArgs,
     pub trackargs: TrackModeArgs,
+    pub batchargs: BatchModeArgs,
+    pub predictargs: PredictModeArgs,
+}
+
+/// Parses value of argument `name`.
+fn value<T: FromStr>(submatches: &ArgMatches, name: &str) -> Result<T> {
+    let value = submatches.value_of(name).unwrap_or("");
+    value.parse::<T>().map_err(|_| Error::Argument(format!("{} isn't a valid value for <{}>", value, name)))
+}
+
+/// Returns --samplerate value, sample time can't be calculated if it is 0.
+fn samplerate(submatches: &ArgMatches) -> Result<u32> {
+    let samplerate = value::<u32>(submatches, "SAMPLERATE")?;
+    if samplerate == 0 {
+        return Err(Error::Argument("--samplerate must be greater than 0".to_string()));
+    }
+
+    Ok(samplerate)
+}
+
+/// Returns --block-samples value if it is given, it must be a positive number.
+fn block_samples(submatches: &ArgMatches) -> Result<Option<usize>> {
+    if !submatches.is_present("BLOCKSAMPLES") {
+        return Ok(None);
+    }
+
+    let samples = value::<usize>(submatches, "BLOCKSAMPLES")?;
+    if samples == 0 {
+        return Err(Error::Argument("--block-samples must be greater than 0".to_string()));
+    }
+
+    Ok(Some(samples))
+}
+
+/// Returns --doppler-update-rate value if it is given, it must be a positive number.
+fn update_rate(submatches: &ArgMatches) -> Result<Option<f64>> {
+    if !submatches.is_present("UPDATERATE") {
+        return Ok(None);
+    }
+
+    let rate = value::<f64>(submatches, "UPDATERATE")?;
+    if rate.is_nan() || rate <= 0.0 {
+        return Err(Error::Argument("--doppler-update-rate must be greater than 0".to_string()));
+    }
+
+    Ok(Some(rate))
+}
+
+/// Returns value that was found from recording or error if it is not known.
+fn required<T>(value: Option<T>, meta: &Option<Recording>, name: &str, arg: &str) -> Result<T> {
+    match value {
+        Some(v) => Ok(v),
+        None => {
+            match *meta {
+                Some(ref m) => Err(Error::Argument(format!("{} is not found from {}, {} must be specified", name, m.source, arg))),
+                None => Err(Error::Argument(format!("{} must be specified", arg))),
+            }
+        }
+    }
+}
+
+fn check_wav_output(args: &CommandArgs) -> Result<()> {
+    if args.outputtype == Some(I8) || args.outputendianness == Some(Endianness::Big) {
+        return Err(Error::Argument("WAV output supports only little endian u8, i16, i32, f32 and f64 types".to_string()));
+    }
+
+    Ok(())
+}
+
+/// Returns recordings in a directory or the file itself.
+/// SigMF recordings are listed by their .sigmf-meta file and already corrected files are skipped.
+fn list_recordings(path: &str) -> Result<Vec<String>> {
+    if !Path::new(path).is_dir() {
+        // shell glob also matches output of an earlier run
+        if recording::is_corrected(path) {
+            warn!("{} is skipped, it is already doppler corrected", path);
+            return Ok(vec![]);
+        }
+
+        return match path.strip_suffix(sigmf::DATA_EXTENSION) {
+            Some(basename) => Ok(vec![format!("{}{}", basename, sigmf::META_EXTENSION)]),
+            None => Ok(vec![path.to_string()]),
+        };
+    }
+
+    let entries = fs::read_dir(path).map_err(|e| Error::io(format!("can't read {}: {}", path, e), &e))?;
+    let mut files: Vec<String> = entries.filter_map(|e| e.ok())
+                                        .map(|e| e.path())
+                                        .filter(|p| p.is_file())
+                                        .filter_map(|p| p.to_str().map(|p| p.to_string()))
+                                        .filter(|p| !p.ends_with(sigmf::DATA_EXTENSION) && !recording::is_corrected(p))
+                                        .filter(|p| recording::detect(p).map(|r| r.datatype.is_some()).unwrap_or(false))
+                                        .collect();
+    files.sort();
+
+    Ok(files)
+}
+
+/// Makes track mode arguments for one recording in batch mode.
+/// Parameters that are not given on the command line are taken from the recording.
+pub fn recording_args(batch: &CommandArgs, path: &str) -> Result<CommandArgs> {
+    let r = recording::detect(path)?;
+    let mut args = batch.clone();
+    args.mode = Some(TrackMode);
+
+    args.input = r.datafile.clone();
+    args.inputoffset = r.data_offset;
+    args.inputlength = r.data_size;
+
+    if args.samplerate.is_none() {
+        let samplerate = r.samplerate.ok_or(Error::Format(format!("samplerate is not found from {}", r.source)))?;
+        if samplerate == 0 {
+            return Err(Error::Format(format!("samplerate in {} is 0", r.source)));
+        }
+        args.samplerate = Some(samplerate);
+    }
+
+    if args.inputtype.is_none() {
+        let (inputtype, inputendianness) = r.datatype.ok_or(Error::Format(format!("data type is not found from {}", r.source)))?;
+        args.inputtype = Some(inputtype);
+        args.inputendianness = Some(inputendianness);
+    }
+
+    if args.outputtype.is_none() {
+        args.outputtype = args.inputtype;
+        args.outputendianness = args.inputendianness;
+    }
+
+    if args.trackargs.frequency.is_none() {
+        let frequency = r.frequency.ok_or(Error::Format(format!("center frequency is not found from {}", r.source)))?;
+        args.trackargs.frequency = Some(frequency.round() as u32);
+    }
+
+    args.trackargs.time = Some(r.datetime.ok_or(Error::Format(format!("start time is not found from {}", r.source)))?);
+    args.trackargs.capturefrequency = r.frequency;
+
+    // output has the same format as input
+    let output = recording::corrected_path(path);
+    if output.ends_with(sigmf::META_EXTENSION) {
+        let basename = output.trim_end_matches(sigmf::META_EXTENSION).to_string();
+        args.output = format!("{}{}", basename, sigmf::DATA_EXTENSION);
+        args.trackargs.outputsigmf = Some(basename);
+    }
+    else if output.to_lowercase().ends_with(".wav") {
+        check_wav_output(&args)?;
+        args.output = output.clone();
+        args.trackargs.outputwav = Some(output);
+    }
+    else {
+        args.output = output;
+    }
+
+    Ok(args)
 }
 
-fn parse_location(location: &str) -> Result<Location, String> {
+fn parse_location(location: &str) -> Result<Location> {
     if location.contains("lat") && location.contains("lon") && location.contains("alt"){
         let split = location.split(",");
 
@@ -102,20 +352,70 @@ fn parse_location(location: &str) -> Result<Location, String> {
             }
         }
 
-        if lat.is_some() && lon.is_some() && alt.is_some() {
-            Ok(Location{lat: lat.unwrap(), lon: lon.unwrap(), alt: alt.unwrap()})
+        match (lat, lon, alt) {
+            (Some(lat), Some(lon), Some(alt)) => Ok(Location{lat, lon, alt}),
+            _ => Err(Error::Argument(format!("{} isn't a valid value for --location\n\t[use as: lat=58.64560,lon=23.15163,alt=8]", location))),
         }
-        else {
-            Err(format!("{} isn't a valid value for --location\n\t[use as: lat=58.64560,lon=23.15163,alt=8]", location).to_string())
+    }
+    else {
+        Err(Error::Argument("--location should be defined as: lat=58.64560,lon=23.15163,alt=8".to_string()))
+    }
+}
+
+/// Parses UTC time in Y-m-dTH:M:S format with optional fractional seconds: eg. 2015-01-22T09:07:16.250
+pub fn parse_time(t: &str) -> Result<time::Tm> {
+    let mut split = t.splitn(2, ".");
+    let mut tm = time::strptime(split.next().unwrap(), "%Y-%m-%dT%H:%M:%S").map_err(|e| Error::Argument(e.to_string()))?;
+
+    if let Some(fraction) = split.next() {
+        if fraction.is_empty() || fraction.len() > 9 || !fraction.chars().all(|c| c.is_ascii_digit()) {
+            return Err(Error::Argument(format!("{} has invalid fractional seconds", t)));
         }
+
+        // pad to nanoseconds: .25 -> 250000000
+        let nanoseconds = format!("{:0<9}", fraction);
+        tm.tm_nsec = nanoseconds.parse::<i32>().unwrap();
+    }
+
+    Ok(tm)
+}
+
+/// Parses data type with optional byte order suffix: eg. i16, i16le or i16be.
+/// Little endian is used if byte order is not specified.
+fn parse_datatype(datatype: &str) -> (DataType, Endianness) {
+    let (name, endianness) = if let Some(name) = datatype.strip_suffix("be") {
+        (name, Endianness::Big)
+    }
+    else if let Some(name) = datatype.strip_suffix("le") {
+        (name, Endianness::Little)
     }
     else {
-        Err("--location should be defined as: lat=58.64560,lon=23.15163,alt=8".to_string())
+        (datatype, Endianness::Little)
+    };
+
+    match name {
+        "f32" => (F32, endianness),
+        "i16" => (I16, endianness),
+        "u8" => (U8, endianness),
+        "i8" => (I8, endianness),
+        "i32" => (I32, endianness),
+        "f64" => (F64, endianness),
+        _ => unreachable!()
     }
 }
 
-pub fn args() -> CommandArgs {
-    let datatypes = ["i16", "f32"];
+/// Parses command line arguments. Help and version are printed by clap which exits then.
+pub fn args() -> Result<CommandArgs> {
+    let datatypes = ["i16", "i16le", "i16be",
+                     "f32", "f32le", "f32be",
+                     "u8",
+                     "i8",
+                     "i32", "i32le", "i32be",
+                     "f64", "f64le", "f64be"];
+
+    // file formats and naming conventions are detected from input file
+    let mut intypes = datatypes.to_vec();
+    intypes.push("auto");
 
     let matches = App::new("doppler")
                 .author("Andres Vahter <andres.vahter@gmail.com>")
@@ -137,7 +437,7 @@ pub fn args() -> CommandArgs {
                     .arg(Arg::with_name("INTYPE")
                        .long("intype")
                        .short("i")
-                       .help("IQ data input type")
+                       .help("IQ data input type, multi-byte types can have le or be suffix for byte order (default le)")
                        .required(true)
                        .possible_values(&datatypes)
                        .takes_value(true))
@@ -145,15 +445,44 @@ pub fn args() -> CommandArgs {
                    .arg(Arg::with_name("OUTTYPE")
                        .long("outtype")
                        .short("o")
-                       .help("IQ data output type")
+                       .help("IQ data output type, multi-byte types can have le or be suffix for byte order (default le)")
                        .required(false)
                        .possible_values(&datatypes)
                        .takes_value(true))
 
+                    .arg(Arg::with_name("INPUT")
+                       .long("input")
+                       .help("Read IQ data from file, - is stdin (default -)")
+                       .required(false)
+                       .takes_value(true))
+
+                    .arg(Arg::with_name("OUTPUT")
+                       .long("output")
+                       .help("Write IQ data to file, - is stdout (default -)")
+                       .required(false)
+                       .takes_value(true))
+
                     .arg(Arg::with_name("SHIFT")
                        .long("shift")
                        .help("frequency shift in Hz")
                        .required(true)
+                       .takes_value(true))
+
+                    .arg(Arg::with_name("GAIN")
+                       .long("gain")
+                       .help("Gain in dB that is applied to output before quantization")
+                       .required(false)
+                       .takes_value(true))
+
+                    .arg(Arg::with_name("DITHER")
+                       .long("dither")
+                       .help("Add TPDF dither to integer output types")
+                       .required(false))
+
+                    .arg(Arg::with_name("BLOCKSAMPLES")
+                       .long("block-samples")
+                       .help("Number of IQ samples read and written at once, smaller blocks give lower latency (default 2048)")
+                       .required(false)
                        .takes_value(true)))
 
 
@@ -165,25 +494,65 @@ pub fn args() -> CommandArgs {
                        .long("samplerate")
                        .short("s")
                        .help("IQ data samplerate")
-                       .required(true)
+                       .required(false)
                        .takes_value(true))
 
                     .arg(Arg::with_name("INTYPE")
                        .long("intype")
                        .short("i")
-                       .help("IQ data type")
-                       .required(true)
-                       .possible_values(&datatypes)
+                       .help("IQ data input type, multi-byte types can have le or be suffix for byte order (default le). With auto type, samplerate, frequency and time are detected from --input file")
+                       .required_unless_one(&["SIGMF", "WAV"])
+                       .possible_values(&intypes)
                        .takes_value(true))
 
                    .arg(Arg::with_name("OUTTYPE")
                        .long("outtype")
                        .short("o")
-                       .help("IQ data output type")
+                       .help("IQ data output type, multi-byte types can have le or be suffix for byte order (default le)")
                        .required(false)
                        .possible_values(&datatypes)
                        .takes_value(true))
 
+                    .arg(Arg::with_name("INPUT")
+                       .long("input")
+                       .help("Read IQ data from file, - is stdin (default -)")
+                       .required(false)
+                       .conflicts_with_all(&["SIGMF", "WAV"])
+                       .takes_value(true))
+
+                    .arg(Arg::with_name("OUTPUT")
+                       .long("output")
+                       .help("Write IQ data to file, - is stdout (default -)")
+                       .required(false)
+                       .conflicts_with_all(&["OUTPUTSIGMF", "OUTPUTWAV"])
+                       .takes_value(true))
+
+                    .arg(Arg::with_name("SIGMF")
+                       .long("sigmf")
+                       .help("Read IQ data from SigMF recording: eg. pass.sigmf-meta. Samplerate, input type, frequency and time are taken from metadata if not specified")
+                       .required(false)
+                       .takes_value(true))
+
+                    .arg(Arg::with_name("WAV")
+                       .long("wav")
+                       .help("Read IQ data from 2 channel WAV file. Samplerate, input type, frequency and time are taken from header if not specified")
+                       .required(false)
+                       .conflicts_with("SIGMF")
+                       .takes_value(true))
+
+                    .arg(Arg::with_name("OUTPUTSIGMF")
+                       .long("output-sigmf")
+                       .help("Write output to SigMF recording <basename>.sigmf-data and describe it in <basename>.sigmf-meta instead of stdout")
+                       .required(false)
+                       .takes_value(true))
+
+                    .arg(Arg::with_name("OUTPUTWAV")
+                       .long("output-wav")
+                       .help("Write output to 2 channel WAV file instead of stdout")
+                       .required(false)
+                       .conflicts_with("OUTPUTSIGMF")
+                       .takes_value(true))
+
                     .arg(Arg::with_name("TLEFILE")
                        .long("tlefile")
                        .help("TLE file: eg. http://www.celestrak.com/NORAD/elements/cubesat.txt")
@@ -205,23 +574,231 @@ pub fn args() -> CommandArgs {
 
                     .arg(Arg::with_name("TIME")
                        .long("time")
-                       .help("Observation start time in UTC Y-m-dTH:M:S: eg. 2015-05-13T14:28:48. If not specified current time is used")
+                       .help("Observation start time in UTC Y-m-dTH:M:S[.fff]: eg. 2015-05-13T14:28:48 or 2015-05-13T14:28:48.250. If not specified current time is used")
                        .required(false)
                        .takes_value(true))
 
                     .arg(Arg::with_name("FREQUENCY")
                        .long("frequency")
                        .help("Satellite transmitter frequency in Hz")
+                       .required(false)
+                       .takes_value(true))
+
+                    .arg(Arg::with_name("OFFSET")
+                       .long("offset")
+                       .help("Constant frequency shift in Hz. Can be used to compensate constant offset")
+                       .required(false)
+                       .takes_value(true))
+
+                    .arg(Arg::with_name("CHIRP")
+                       .long("chirp")
+                       .help("Sweep doppler correction smoothly between doppler updates instead of using one frequency until the next update")
+                       .required(false))
+
+                    .arg(Arg::with_name("DOPPLERRATE")
+                       .long("doppler-rate")
+                       .help("Remove also doppler rate, correction frequency changes between updates at the rate predicted from range acceleration")
+                       .conflicts_with("CHIRP")
+                       .required(false))
+
+                    .arg(Arg::with_name("GAIN")
+                       .long("gain")
+                       .help("Gain in dB that is applied to output before quantization")
+                       .required(false)
+                       .takes_value(true))
+
+                    .arg(Arg::with_name("DITHER")
+                       .long("dither")
+                       .help("Add TPDF dither to integer output types")
+                       .required(false))
+
+                    .arg(Arg::with_name("THREADS")
+                       .long("threads")
+                       .help("Number of threads for correcting recordings, number of CPU cores is used by default. Results do not depend on it")
+                       .required(false)
+                       .takes_value(true))
+
+                    .arg(Arg::with_name("BLOCKSAMPLES")
+                       .long("block-samples")
+                       .help("Number of IQ samples read and written at once, smaller blocks give lower latency (default 2048)")
+                       .required(false)
+                       .takes_value(true))
+
+                    .arg(Arg::with_name("UPDATERATE")
+                       .long("doppler-update-rate")
+                       .help("How many times per second doppler correction is updated, independent of block size (default 100)")
+                       .required(false)
+                       .takes_value(true)))
+
+                .subcommand(SubCommand::with_name("batch")
+                    .setting(AppSettings::AllowLeadingHyphen) // allow negative values like --offset -5000
+                    .about("Doppler tracking mode for a set of recordings, start time and other parameters are detected from each file")
+
+                    .arg(Arg::with_name("SAMPLERATE")
+                       .long("samplerate")
+                       .short("s")
+                       .help("IQ data samplerate, detected from files if not specified")
+                       .required(false)
+                       .takes_value(true))
+
+                    .arg(Arg::with_name("INTYPE")
+                       .long("intype")
+                       .short("i")
+                       .help("IQ data input type, detected from files if not specified")
+                       .required(false)
+                       .possible_values(&datatypes)
+                       .takes_value(true))
+
+                   .arg(Arg::with_name("OUTTYPE")
+                       .long("outtype")
+                       .short("o")
+                       .help("IQ data output type, multi-byte types can have le or be suffix for byte order (default le)")
+                       .required(false)
+                       .possible_values(&datatypes)
+                       .takes_value(true))
+
+                    .arg(Arg::with_name("TLEFILE")
+                       .long("tlefile")
+                       .help("TLE file: eg. http://www.celestrak.com/NORAD/elements/cubesat.txt")
+                       .required(true)
+                       .takes_value(true))
+
+                    .arg(Arg::with_name("TLENAME")
+                       .long("tlename")
+                       .help("TLE name in TLE file: eg. ESTCUBE 1")
                        .required(true)
                        .takes_value(true))
 
+                    .arg(Arg::with_name("LOCATION")
+                       .long("location")
+                       .help("Observer location (lat=<deg>,lon=<deg>,alt=<m>): eg. lat=58.64560,lon=23.15163,alt=8")
+                       .required(true)
+                       .use_delimiter(false)
+                       .takes_value(true))
+
+                    .arg(Arg::with_name("FREQUENCY")
+                       .long("frequency")
+                       .help("Satellite transmitter frequency in Hz, recording center frequency is used if not specified")
+                       .required(false)
+                       .takes_value(true))
+
                     .arg(Arg::with_name("OFFSET")
                        .long("offset")
                        .help("Constant frequency shift in Hz. Can be used to compensate constant offset")
                        .required(false)
+                       .takes_value(true))
+
+                    .arg(Arg::with_name("CHIRP")
+                       .long("chirp")
+                       .help("Sweep doppler correction smoothly between doppler updates instead of using one frequency until the next update")
+                       .required(false))
+
+                    .arg(Arg::with_name("DOPPLERRATE")
+                       .long("doppler-rate")
+                       .help("Remove also doppler rate, correction frequency changes between updates at the rate predicted from range acceleration")
+                       .conflicts_with("CHIRP")
+                       .required(false))
+
+                    .arg(Arg::with_name("GAIN")
+                       .long("gain")
+                       .help("Gain in dB that is applied to output before quantization")
+                       .required(false)
+                       .takes_value(true))
+
+                    .arg(Arg::with_name("DITHER")
+                       .long("dither")
+                       .help("Add TPDF dither to integer output types")
+                       .required(false))
+
+                    .arg(Arg::with_name("THREADS")
+                       .long("threads")
+                       .help("Number of threads for correcting recordings, number of CPU cores is used by default. Results do not depend on it")
+                       .required(false)
+                       .takes_value(true))
+
+                    .arg(Arg::with_name("BLOCKSAMPLES")
+                       .long("block-samples")
+                       .help("Number of IQ samples read and written at once, smaller blocks give lower latency (default 2048)")
+                       .required(false)
+                       .takes_value(true))
+
+                    .arg(Arg::with_name("UPDATERATE")
+                       .long("doppler-update-rate")
+                       .help("How many times per second doppler correction is updated, independent of block size (default 100)")
+                       .required(false)
+                       .takes_value(true))
+
+                    .arg(Arg::with_name("FILES")
+                       .help("Recordings or directories of recordings: SigMF, WAV, GQRX raw or .cu8, .cs16, .cf32 etc. files. Corrected file is written next to each recording with _doppler suffix")
+                       .required(true)
+                       .multiple(true)))
+
+                .subcommand(SubCommand::with_name("predict")
+                    .about("Prints predicted doppler curve without IQ data")
+
+                    .arg(Arg::with_name("TLEFILE")
+                       .long("tlefile")
+                       .help("TLE file: eg. http://www.celestrak.com/NORAD/elements/cubesat.txt")
+                       .required(true)
+                       .takes_value(true))
+
+                    .arg(Arg::with_name("TLENAME")
+                       .long("tlename")
+                       .help("TLE name in TLE file: eg. ESTCUBE 1")
+                       .required(true)
+                       .takes_value(true))
+
+                    .arg(Arg::with_name("LOCATION")
+                       .long("location")
+                       .help("Observer location (lat=<deg>,lon=<deg>,alt=<m>): eg. lat=58.64560,lon=23.15163,alt=8")
+                       .required(true)
+                       .use_delimiter(false)
+                       .takes_value(true))
+
+                    .arg(Arg::with_name("FREQUENCY")
+                       .long("frequency")
+                       .help("Satellite transmitter frequency in Hz")
+                       .required(true)
+                       .takes_value(true))
+
+                    .arg(Arg::with_name("TIME")
+                       .long("time")
+                       .help("Start time in UTC Y-m-dTH:M:S[.fff]: eg. 2015-05-13T14:28:48. If not specified current time is used")
+                       .required(false)
+                       .takes_value(true))
+
+                    .arg(Arg::with_name("DURATION")
+                       .long("duration")
+                       .help("Length of the prediction in seconds (default 900)")
+                       .required(false)
+                       .takes_value(true))
+
+                    .arg(Arg::with_name("STEP")
+                       .long("step")
+                       .help("Time between predictions in seconds (default 1)")
+                       .required(false)
+                       .takes_value(true))
+
+                    .arg(Arg::with_name("FORMAT")
+                       .long("format")
+                       .help("Output format (default csv)")
+                       .required(false)
+                       .possible_values(&["csv", "json"])
+                       .takes_value(true))
+
+                    .arg(Arg::with_name("OUTPUT")
+                       .long("output")
+                       .help("Write predictions to file, - is stdout (default -)")
+                       .required(false)
                        .takes_value(true)))
 
-                .get_matches();
+                .get_matches_safe();
+
+    let matches = match matches {
+        Ok(m) => m,
+        Err(ref e) if e.kind == ErrorKind::HelpDisplayed || e.kind == ErrorKind::VersionDisplayed => e.exit(),
+        Err(e) => return Err(Error::Argument(e.message)),
+    };
 
 
     let mut args = CommandArgs {
@@ -229,7 +806,18 @@ pub fn args() -> CommandArgs {
 
                     samplerate : None,
                     inputtype : None,
+                    inputendianness : None,
                     outputtype: None,
+                    outputendianness : None,
+                    gain : None,
+                    dither : false,
+                    threads : None,
+                    blocksamples : None,
+
+                    input : STDIO.to_string(),
+                    output : STDIO.to_string(),
+                    inputoffset : 0,
+                    inputlength : None,
 
                     constargs : ConstModeArgs {
                         shift: None,
@@ -242,6 +830,22 @@ pub fn args() -> CommandArgs {
                         time : None,
                         frequency : None,
                         offset : None,
+                        chirp : false,
+                        dopplerrate : false,
+                        updaterate : None,
+                        capturefrequency : None,
+                        outputsigmf : None,
+                        outputwav : None,
+                    },
+
+                    batchargs : BatchModeArgs {
+                        files : vec![],
+                    },
+
+                    predictargs : PredictModeArgs {
+                        duration : 900.0,
+                        step : 1.0,
+                        format : PredictFormat::Csv,
                     },
                 };
 
@@ -250,88 +854,274 @@ pub fn args() -> CommandArgs {
         Some("const")   => {
             args.mode = Some(ConstMode);
             let submatches = matches.subcommand_matches("const").unwrap();
-            args.samplerate = Some(value_t_or_exit!(submatches.value_of("SAMPLERATE"), u32));
+            args.samplerate = Some(samplerate(submatches)?);
 
-            match submatches.value_of("INTYPE").unwrap() {
-                "f32" => {args.inputtype = Some(F32);},
-                "i16" => {args.inputtype = Some(I16);},
-                _ => unreachable!()
-            }
+            let (inputtype, inputendianness) = parse_datatype(submatches.value_of("INTYPE").unwrap());
+            args.inputtype = Some(inputtype);
+            args.inputendianness = Some(inputendianness);
 
             if submatches.is_present("OUTTYPE") {
-                match submatches.value_of("OUTTYPE").unwrap() {
-                    "f32" => {args.outputtype = Some(F32);},
-                    "i16" => {args.outputtype = Some(I16);},
-                    _ => unreachable!()
-                }
+                let (outputtype, outputendianness) = parse_datatype(submatches.value_of("OUTTYPE").unwrap());
+                args.outputtype = Some(outputtype);
+                args.outputendianness = Some(outputendianness);
             }
             else {
                 args.outputtype = args.inputtype;
+                args.outputendianness = args.inputendianness;
+            }
+
+            args.input = submatches.value_of("INPUT").unwrap_or(STDIO).to_string();
+            args.output = submatches.value_of("OUTPUT").unwrap_or(STDIO).to_string();
+
+            args.constargs.shift = Some(value::<i32>(submatches, "SHIFT")?);
+
+            if submatches.is_present("GAIN") {
+                args.gain = Some(value::<f32>(submatches, "GAIN")?);
             }
 
-            args.constargs.shift = Some(value_t_or_exit!(submatches.value_of("SHIFT"), i32));
+            args.dither = submatches.is_present("DITHER");
+            args.blocksamples = block_samples(submatches)?;
         },
 
 
         Some("track") => {
             args.mode = Some(TrackMode);
             let submatches = matches.subcommand_matches("track").unwrap();
-            args.samplerate = Some(value_t_or_exit!(submatches.value_of("SAMPLERATE"), u32));
 
-            match submatches.value_of("INTYPE").unwrap() {
-                "f32" => {args.inputtype = Some(F32);},
-                "i16" => {args.inputtype = Some(I16);},
-                _ => unreachable!()
+            let autotype = submatches.value_of("INTYPE") == Some("auto");
+            if autotype && submatches.value_of("INPUT").unwrap_or(STDIO) == STDIO {
+                return Err(Error::Argument("--intype auto needs input file, use --input".to_string()));
+            }
+
+            let meta = if submatches.is_present("SIGMF") {
+                Some(recording::from_sigmf(submatches.value_of("SIGMF").unwrap()))
+            }
+            else if submatches.is_present("WAV") {
+                Some(recording::from_wav(submatches.value_of("WAV").unwrap()))
+            }
+            else if autotype {
+                Some(recording::detect(submatches.value_of("INPUT").unwrap()))
+            }
+            else {
+                None
+            };
+
+            let meta = match meta {
+                Some(m) => {
+                    let m = m?;
+                    args.input = m.datafile.clone();
+                    args.inputoffset = m.data_offset;
+                    args.inputlength = m.data_size;
+                    Some(m)
+                },
+                None => {
+                    args.input = submatches.value_of("INPUT").unwrap_or(STDIO).to_string();
+                    None
+                },
+            };
+
+            if submatches.is_present("SAMPLERATE") {
+                args.samplerate = Some(samplerate(submatches)?);
+            }
+            else {
+                args.samplerate = Some(required(meta.as_ref().and_then(|m| m.samplerate), &meta, "samplerate", "--samplerate")?);
+            }
+
+            if submatches.is_present("INTYPE") && !autotype {
+                let (inputtype, inputendianness) = parse_datatype(submatches.value_of("INTYPE").unwrap());
+                args.inputtype = Some(inputtype);
+                args.inputendianness = Some(inputendianness);
+            }
+            else {
+                let (inputtype, inputendianness) = required(meta.as_ref().and_then(|m| m.datatype), &meta, "data type", "--intype")?;
+                args.inputtype = Some(inputtype);
+                args.inputendianness = Some(inputendianness);
             }
 
             if submatches.is_present("OUTTYPE") {
-                match submatches.value_of("OUTTYPE").unwrap() {
-                    "f32" => {args.outputtype = Some(F32);},
-                    "i16" => {args.outputtype = Some(I16);},
-                    _ => unreachable!()
-                }
+                let (outputtype, outputendianness) = parse_datatype(submatches.value_of("OUTTYPE").unwrap());
+                args.outputtype = Some(outputtype);
+                args.outputendianness = Some(outputendianness);
             }
             else {
                 args.outputtype = args.inputtype;
+                args.outputendianness = args.inputendianness;
             }
 
             if submatches.is_present("OFFSET") {
-                args.trackargs.offset = Some(value_t_or_exit!(submatches.value_of("OFFSET"), i32));
+                args.trackargs.offset = Some(value::<i32>(submatches, "OFFSET")?);
             }
 
             if submatches.is_present("TIME") {
-                let tm = time::strptime(submatches.value_of("TIME").unwrap(), "%Y-%m-%dT%H:%M:%S");
-                match tm {
-                    Ok(_) => {},
-                    Err(e) => {
-                        error!("{}.", e);
-                        error!("--time should be defined in Y-m-dTH:M:S format: eg. 2015-05-13T14:28:48");
-                        exit(1);
-                    },
-                };
+                let tm = parse_time(submatches.value_of("TIME").unwrap()).map_err(|e| {
+                    Error::Argument(format!("{}, --time should be defined in Y-m-dTH:M:S[.fff] format: eg. 2015-05-13T14:28:48 or 2015-05-13T14:28:48.250", e))
+                })?;
+
+                args.trackargs.time = Some(tm);
+            }
+            else {
+                args.trackargs.time = meta.as_ref().and_then(|m| m.datetime);
+
+                // a recording can't be processed in realtime mode
+                if meta.is_some() {
+                    args.trackargs.time = Some(required(args.trackargs.time, &meta, "start time", "--time")?);
+                }
+            }
+
+            args.trackargs.chirp = submatches.is_present("CHIRP");
+            args.trackargs.dopplerrate = submatches.is_present("DOPPLERRATE");
+            args.trackargs.capturefrequency = meta.as_ref().and_then(|m| m.frequency);
+
+            if submatches.is_present("OUTPUTSIGMF") {
+                let basename = submatches.value_of("OUTPUTSIGMF").unwrap();
+                args.trackargs.outputsigmf = Some(basename.to_string());
+                args.output = format!("{}{}", basename, sigmf::DATA_EXTENSION);
+            }
+            else if submatches.is_present("OUTPUTWAV") {
+                args.output = submatches.value_of("OUTPUTWAV").unwrap().to_string();
+            }
+            else {
+                args.output = submatches.value_of("OUTPUT").unwrap_or(STDIO).to_string();
+            }
+
+            if submatches.is_present("OUTPUTWAV") {
+                check_wav_output(&args)?;
+
+                args.trackargs.outputwav = Some(submatches.value_of("OUTPUTWAV").unwrap().to_string());
+            }
+
+            if submatches.is_present("GAIN") {
+                args.gain = Some(value::<f32>(submatches, "GAIN")?);
+            }
+
+            args.dither = submatches.is_present("DITHER");
 
-                args.trackargs.time = Some(tm.unwrap());
+            if submatches.is_present("THREADS") {
+                args.threads = Some(value::<usize>(submatches, "THREADS")?.max(1));
             }
 
+            args.blocksamples = block_samples(submatches)?;
+            args.trackargs.updaterate = update_rate(submatches)?;
+
             args.trackargs.tlefile = Some(submatches.value_of("TLEFILE").unwrap().to_string());
             args.trackargs.tlename = Some(submatches.value_of("TLENAME").unwrap().to_string());
-            args.trackargs.frequency = Some(value_t_or_exit!(submatches.value_of("FREQUENCY"), u32));
-
-            let location = parse_location(&submatches.value_of("LOCATION").unwrap().to_string());
-            match location {
-                Ok(loc) => { args.trackargs.location = Some(loc);},
-                Err(e) => {
-                    error!("{}.", e);
-                    exit(1);
+
+            if submatches.is_present("FREQUENCY") {
+                args.trackargs.frequency = Some(value::<u32>(submatches, "FREQUENCY")?);
+            }
+            else {
+                let frequency = required(meta.as_ref().and_then(|m| m.frequency), &meta, "center frequency", "--frequency")?;
+                args.trackargs.frequency = Some(frequency.round() as u32);
+            }
+
+            args.trackargs.location = Some(parse_location(submatches.value_of("LOCATION").unwrap())?);
+        },
+
+        Some("batch") => {
+            args.mode = Some(BatchMode);
+            let submatches = matches.subcommand_matches("batch").unwrap();
+
+            if submatches.is_present("SAMPLERATE") {
+                args.samplerate = Some(samplerate(submatches)?);
+            }
+
+            if submatches.is_present("INTYPE") {
+                let (inputtype, inputendianness) = parse_datatype(submatches.value_of("INTYPE").unwrap());
+                args.inputtype = Some(inputtype);
+                args.inputendianness = Some(inputendianness);
+            }
+
+            if submatches.is_present("OUTTYPE") {
+                let (outputtype, outputendianness) = parse_datatype(submatches.value_of("OUTTYPE").unwrap());
+                args.outputtype = Some(outputtype);
+                args.outputendianness = Some(outputendianness);
+            }
+
+            if submatches.is_present("OFFSET") {
+                args.trackargs.offset = Some(value::<i32>(submatches, "OFFSET")?);
+            }
+
+            if submatches.is_present("FREQUENCY") {
+                args.trackargs.frequency = Some(value::<u32>(submatches, "FREQUENCY")?);
+            }
+
+            args.trackargs.chirp = submatches.is_present("CHIRP");
+            args.trackargs.dopplerrate = submatches.is_present("DOPPLERRATE");
+
+            if submatches.is_present("GAIN") {
+                args.gain = Some(value::<f32>(submatches, "GAIN")?);
+            }
+
+            args.dither = submatches.is_present("DITHER");
+
+            if submatches.is_present("THREADS") {
+                args.threads = Some(value::<usize>(submatches, "THREADS")?.max(1));
+            }
+
+            args.blocksamples = block_samples(submatches)?;
+            args.trackargs.updaterate = update_rate(submatches)?;
+
+            args.trackargs.tlefile = Some(submatches.value_of("TLEFILE").unwrap().to_string());
+            args.trackargs.tlename = Some(submatches.value_of("TLENAME").unwrap().to_string());
+
+            args.trackargs.location = Some(parse_location(submatches.value_of("LOCATION").unwrap())?);
+
+            // .sigmf-meta and .sigmf-data of the same recording or overlapping globs give it only once
+            for file in submatches.values_of("FILES").unwrap() {
+                for recording in list_recordings(file)? {
+                    if !args.batchargs.files.contains(&recording) {
+                        args.batchargs.files.push(recording);
+                    }
                 }
             }
+
+            if args.batchargs.files.is_empty() {
+                return Err(Error::Argument("no recordings to correct were found".to_string()));
+            }
+        },
+
+        Some("predict") => {
+            args.mode = Some(PredictMode);
+            let submatches = matches.subcommand_matches("predict").unwrap();
+
+            args.trackargs.tlefile = Some(submatches.value_of("TLEFILE").unwrap().to_string());
+            args.trackargs.tlename = Some(submatches.value_of("TLENAME").unwrap().to_string());
+            args.trackargs.location = Some(parse_location(submatches.value_of("LOCATION").unwrap())?);
+            args.trackargs.frequency = Some(value::<u32>(submatches, "FREQUENCY")?);
+
+            if submatches.is_present("TIME") {
+                let tm = parse_time(submatches.value_of("TIME").unwrap()).map_err(|e| {
+                    Error::Argument(format!("{}, --time should be defined in Y-m-dTH:M:S[.fff] format: eg. 2015-05-13T14:28:48 or 2015-05-13T14:28:48.250", e))
+                })?;
+
+                args.trackargs.time = Some(tm);
+            }
+
+            if submatches.is_present("DURATION") {
+                args.predictargs.duration = value::<f64>(submatches, "DURATION")?;
+                if !args.predictargs.duration.is_finite() || args.predictargs.duration < 0.0 {
+                    return Err(Error::Argument("--duration must be a finite number that isn't negative".to_string()));
+                }
+            }
+
+            if submatches.is_present("STEP") {
+                args.predictargs.step = value::<f64>(submatches, "STEP")?;
+                if args.predictargs.step.is_nan() || args.predictargs.step <= 0.0 {
+                    return Err(Error::Argument("--step must be greater than 0".to_string()));
+                }
+            }
+
+            if submatches.value_of("FORMAT") == Some("json") {
+                args.predictargs.format = PredictFormat::Json;
+            }
+
+            args.output = submatches.value_of("OUTPUT").unwrap_or(STDIO).to_string();
         },
 
         _ => {
-            info!("no arguments provided, try with doppler -h");
-            exit(1);
+            return Err(Error::Argument("no arguments provided, try with doppler -h".to_string()));
         }
     }
 
-    args
+    Ok(args)
 }
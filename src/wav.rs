@@ -0,0 +1,332 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+// WAV (RIFF) IQ recording support. I and Q are stored as left and right channel.
+// SDR# and SDRuno add an auxi chunk that holds recording start time and center frequency.
+
+use time;
+use usage::DataType;
+use error::{Error, Result};
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_IEEE_FLOAT: u16 = 3;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+// header written by write_header: RIFF, fmt and data chunk headers
+pub const HEADER_SIZE: u64 = 44;
+
+// fmt and auxi chunks are a few dozen bytes, larger sizes come from a broken header
+const MAX_PARSED_CHUNK_SIZE: u64 = 1024;
+
+/// Recording parameters that are read from a WAV header.
+pub struct Header {
+    pub datatype: DataType,
+    pub samplerate: u32,
+    pub frequency: Option<f64>,
+    pub datetime: Option<time::Tm>,
+
+    // position and length of IQ data in the file, length is not known for streamed files
+    pub data_offset: u64,
+    pub data_size: Option<u64>,
+}
+
+fn u16_le(b: &[u8]) -> u16 {
+    u16::from_le_bytes([b[0], b[1]])
+}
+
+fn u32_le(b: &[u8]) -> u32 {
+    u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+}
+
+fn read_bytes<R: Read>(reader: &mut R, size: usize) -> Result<Vec<u8>> {
+    let mut buf = vec![0; size];
+    reader.read_exact(&mut buf).map_err(|e| Error::Format(format!("WAV header is truncated: {}", e)))?;
+    Ok(buf)
+}
+
+fn parse_datatype(format: u16, bits: u16) -> Result<DataType> {
+    match (format, bits) {
+        (FORMAT_PCM, 8) => Ok(DataType::U8),
+        (FORMAT_PCM, 16) => Ok(DataType::I16),
+        (FORMAT_PCM, 32) => Ok(DataType::I32),
+        (FORMAT_IEEE_FLOAT, 32) => Ok(DataType::F32),
+        (FORMAT_IEEE_FLOAT, 64) => Ok(DataType::F64),
+        _ => Err(Error::Format(format!("WAV format {} with {} bits per sample is not supported", format, bits))),
+    }
+}
+
+/// Converts Windows SYSTEMTIME structure from auxi chunk to UTC time.
+fn parse_systemtime(b: &[u8]) -> Option<time::Tm> {
+    let year = u16_le(&b[0..]) as i32;
+    if year == 0 {
+        return None;
+    }
+
+    // b[4..6] is day of week, it is not needed
+    Some(time::Tm {
+        tm_year: year - 1900,
+        tm_mon: u16_le(&b[2..]) as i32 - 1,
+        tm_mday: u16_le(&b[6..]) as i32,
+        tm_hour: u16_le(&b[8..]) as i32,
+        tm_min: u16_le(&b[10..]) as i32,
+        tm_sec: u16_le(&b[12..]) as i32,
+        tm_nsec: u16_le(&b[14..]) as i32 * 1_000_000,
+        tm_wday: 0,
+        tm_yday: 0,
+        tm_isdst: 0,
+        tm_utcoff: 0,
+    })
+}
+
+/// Parses WAV header and leaves reader at the beginning of IQ data.
+pub fn parse_header<R: Read>(reader: &mut R) -> Result<Header> {
+    let riff = read_bytes(reader, 12)?;
+    if &riff[0..4] != b"RIFF" || &riff[8..12] != b"WAVE" {
+        return Err(Error::Format("not a WAV file".to_string()));
+    }
+
+    let mut offset: u64 = 12;
+    let mut fmt: Option<(DataType, u32)> = None;
+    let mut frequency = None;
+    let mut datetime = None;
+
+    loop {
+        let chunk = read_bytes(reader, 8)?;
+        let size = u32_le(&chunk[4..]);
+        offset += 8;
+
+        if &chunk[0..4] == b"data" {
+            let (datatype, samplerate) = fmt.ok_or(Error::Format("WAV file does not have fmt chunk before data".to_string()))?;
+
+            return Ok(Header {
+                datatype,
+                samplerate,
+                frequency,
+                datetime,
+                data_offset: offset,
+                // size is left 0 or 0xFFFFFFFF by recorders that stream to pipes
+                data_size: if size == 0 || size == 0xFFFFFFFF {None} else {Some(size as u64)},
+            });
+        }
+
+        // chunks are word aligned
+        let padded = size as u64 + (size as u64 & 1);
+        offset += padded;
+
+        let id = &chunk[0..4];
+        if id != b"fmt " && id != b"auxi" {
+            // other chunks, eg. LIST, are skipped without reading them to memory
+            let skipped = io::copy(&mut reader.by_ref().take(padded), &mut io::sink())
+                .map_err(|e| Error::Format(format!("WAV header is truncated: {}", e)))?;
+            if skipped < padded {
+                return Err(Error::Format("WAV header is truncated".to_string()));
+            }
+            continue;
+        }
+
+        if padded > MAX_PARSED_CHUNK_SIZE {
+            return Err(Error::Format(format!("WAV {} chunk is too large: {} bytes", String::from_utf8_lossy(id), size)));
+        }
+        let body = read_bytes(reader, padded as usize)?;
+
+        match id {
+            b"fmt " => {
+                if body.len() < 16 {
+                    return Err(Error::Format("WAV fmt chunk is too short".to_string()));
+                }
+
+                let channels = u16_le(&body[2..]);
+                if channels != 2 {
+                    return Err(Error::Format(format!("WAV file has {} channels, IQ data must have 2", channels)));
+                }
+
+                let mut format = u16_le(&body[0..]);
+                if format == FORMAT_EXTENSIBLE && body.len() >= 26 {
+                    // first two bytes of subformat GUID hold the actual format
+                    format = u16_le(&body[24..]);
+                }
+
+                let samplerate = u32_le(&body[4..]);
+                if samplerate == 0 {
+                    return Err(Error::Format("WAV file has samplerate 0".to_string()));
+                }
+
+                fmt = Some((parse_datatype(format, u16_le(&body[14..]))?, samplerate));
+            },
+            // StartTime, StopTime, CenterFreq
+            b"auxi" if body.len() >= 36 => {
+                datetime = parse_systemtime(&body[0..16]);
+                let center = u32_le(&body[32..]);
+                if center != 0 {
+                    frequency = Some(center as f64);
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+pub fn read_header(wavfile: &str) -> Result<Header> {
+    let file = File::open(wavfile).map_err(|e| Error::io(format!("can't open {}: {}", wavfile, e), &e))?;
+    parse_header(&mut io::BufReader::new(file)).map_err(|e| Error::Format(format!("{}: {}", wavfile, e)))
+}
+
+/// Writes 44 byte WAV header. Sizes are written as 0xFFFFFFFF if data size is not known,
+/// they can be fixed later with finalize.
+pub fn write_header<W: Write>(writer: &mut W, datatype: DataType, samplerate: u32, data_size: Option<u64>) -> Result<()> {
+    let (format, bits): (u16, u16) = match datatype {
+        DataType::U8 => (FORMAT_PCM, 8),
+        DataType::I16 => (FORMAT_PCM, 16),
+        DataType::I32 => (FORMAT_PCM, 32),
+        DataType::F32 => (FORMAT_IEEE_FLOAT, 32),
+        DataType::F64 => (FORMAT_IEEE_FLOAT, 64),
+        DataType::I8 => return Err(Error::Format("WAV does not support signed 8 bit samples, use u8 instead".to_string())),
+    };
+
+    let block_align = datatype.sample_size() as u16;
+    let (riff_size, data_size) = match data_size {
+        Some(size) if size + HEADER_SIZE - 8 <= 0xFFFFFFFF => ((size + HEADER_SIZE - 8) as u32, size as u32),
+        _ => (0xFFFFFFFF, 0xFFFFFFFF),
+    };
+
+    let mut header: Vec<u8> = Vec::with_capacity(HEADER_SIZE as usize);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&riff_size.to_le_bytes());
+    header.extend_from_slice(b"WAVE");
+    header.extend_from_slice(b"fmt ");
+    header.extend_from_slice(&16_u32.to_le_bytes());
+    header.extend_from_slice(&format.to_le_bytes());
+    header.extend_from_slice(&2_u16.to_le_bytes());
+    header.extend_from_slice(&samplerate.to_le_bytes());
+    header.extend_from_slice(&(samplerate * block_align as u32).to_le_bytes());
+    header.extend_from_slice(&block_align.to_le_bytes());
+    header.extend_from_slice(&bits.to_le_bytes());
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&data_size.to_le_bytes());
+
+    writer.write_all(&header).map_err(|e| Error::io(format!("can't write WAV header: {}", e), &e))
+}
+
+/// Fixes RIFF and data chunk sizes in a WAV file that was written with unknown data size.
+pub fn finalize(wavfile: &str) -> Result<()> {
+    let mut file = OpenOptions::new().write(true).open(wavfile).map_err(|e| Error::io(format!("can't open {}: {}", wavfile, e), &e))?;
+    let length = file.metadata().map_err(|e| Error::io(format!("can't read {}: {}", wavfile, e), &e))?.len();
+
+    if length > 0xFFFFFFFF {
+        warn!("{} is larger than 4 GiB, WAV header sizes are left unknown", wavfile);
+        return Ok(());
+    }
+
+    let data_size = length.saturating_sub(HEADER_SIZE) as u32;
+    let riff_size = length.saturating_sub(8) as u32;
+
+    let mut patch = |position: u64, value: u32| -> io::Result<()> {
+        file.seek(SeekFrom::Start(position))?;
+        file.write_all(&value.to_le_bytes())
+    };
+
+    patch(4, riff_size).and_then(|_| patch(HEADER_SIZE - 4, data_size)).map_err(|e| Error::io(format!("can't write {}: {}", wavfile, e), &e))
+}
+
+#[test]
+fn test_wav_header() {
+    let mut wav: Vec<u8> = Vec::new();
+    write_header(&mut wav, DataType::I16, 1024000, Some(4096)).unwrap();
+    assert_eq!(wav.len() as u64, HEADER_SIZE);
+    assert!(write_header(&mut Vec::new(), DataType::I8, 1024000, None).is_err());
+
+    let header = parse_header(&mut &wav[..]).unwrap();
+    assert!(header.datatype == DataType::I16);
+    assert_eq!(header.samplerate, 1024000);
+    assert_eq!(header.data_offset, HEADER_SIZE);
+    assert_eq!(header.data_size, Some(4096));
+    assert!(header.frequency.is_none());
+    assert!(header.datetime.is_none());
+
+    // streamed header does not have size
+    let mut wav: Vec<u8> = Vec::new();
+    write_header(&mut wav, DataType::F32, 256000, None).unwrap();
+    let header = parse_header(&mut &wav[..]).unwrap();
+    assert!(header.datatype == DataType::F32);
+    assert_eq!(header.data_size, None);
+
+    let mut wav: Vec<u8> = Vec::new();
+    write_header(&mut wav, DataType::I16, 0, None).unwrap();
+    assert!(parse_header(&mut &wav[..]).is_err());
+}
+
+#[test]
+fn test_wav_auxi() {
+    let mut wav: Vec<u8> = Vec::new();
+    write_header(&mut wav, DataType::I16, 2048000, Some(0)).unwrap();
+    let (head, data) = wav.split_at(36);
+
+    // SDR# auxi chunk: StartTime 2015-01-22 09:07:16.250, StopTime, CenterFreq 437500000 Hz
+    let mut auxi: Vec<u8> = Vec::new();
+    auxi.extend_from_slice(b"auxi");
+    auxi.extend_from_slice(&36_u32.to_le_bytes());
+    for v in &[2015_u16, 1, 4, 22, 9, 7, 16, 250] {
+        auxi.extend_from_slice(&v.to_le_bytes());
+    }
+    auxi.extend_from_slice(&[0; 16]);
+    auxi.extend_from_slice(&437500000_u32.to_le_bytes());
+
+    let wav = [head, &auxi[..], data].concat();
+    let header = parse_header(&mut &wav[..]).unwrap();
+    assert_eq!(header.data_offset, HEADER_SIZE + auxi.len() as u64);
+    assert_eq!(header.frequency, Some(437500000.0));
+
+    let datetime = header.datetime.unwrap();
+    assert_eq!(datetime.to_timespec(), ::usage::parse_time("2015-01-22T09:07:16.250").unwrap().to_timespec());
+}
+
+#[test]
+fn test_wav_skip_chunks() {
+    let mut wav: Vec<u8> = Vec::new();
+    write_header(&mut wav, DataType::I16, 2048000, Some(0)).unwrap();
+    let (head, data) = wav.split_at(36);
+
+    // odd sized LIST chunk is padded to word boundary
+    let mut list: Vec<u8> = Vec::new();
+    list.extend_from_slice(b"LIST");
+    list.extend_from_slice(&5_u32.to_le_bytes());
+    list.extend_from_slice(b"INFO\0\0");
+
+    let wav = [head, &list[..], data].concat();
+    let header = parse_header(&mut &wav[..]).unwrap();
+    assert_eq!(header.data_offset, HEADER_SIZE + list.len() as u64);
+
+    // declared size of a skipped chunk is not allocated, truncated file is an error
+    let mut junk: Vec<u8> = Vec::new();
+    junk.extend_from_slice(b"junk");
+    junk.extend_from_slice(&0xFFFFFFF0_u32.to_le_bytes());
+
+    let wav = [head, &junk[..], data].concat();
+    assert!(parse_header(&mut &wav[..]).is_err());
+}